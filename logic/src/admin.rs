@@ -0,0 +1,248 @@
+use near_sdk::json_types::U128;
+use near_sdk::{
+    env, near, near_bindgen, require, AccountId, Gas, NearToken, Promise, PromiseResult,
+};
+
+use crate::arena::ext_ft_contract;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+pub const GAS_FOR_FT_BALANCE_OF: Gas = Gas::from_tgas(5);
+pub const GAS_FOR_ADMIN_WITHDRAW_CALLBACK: Gas = Gas::from_tgas(20);
+pub const GAS_FOR_BALANCE_INVARIANT_CALLBACK: Gas = Gas::from_tgas(10);
+
+// Result of comparing what the contract holds with what it owes
+#[near(serializers = [json])]
+pub struct BalanceInvariant {
+    pub token_id: AccountId,
+    pub ft_balance: U128,
+    // Stakes, unclaimed winnings, bonds, pending refunds and protocol fees not withdrawn yet
+    pub total_owed: U128,
+    pub holds: bool,
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Public Method - but only callable by env::current_account_id()
+    // withdraws tokens that are not owed to any bettor, e.g. tokens sent with a plain ft_transfer.
+    // Works on any token, the default token if none is given.
+    #[private]
+    pub fn admin_withdraw(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        token_id: Option<AccountId>,
+    ) -> Promise {
+        require!(amount.0 > 0, "Amount must be positive");
+        let token_id = self.internal_token_or_default(token_id);
+
+        // The free balance is only known once the FT contract has reported ours
+        ext_ft_contract::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ADMIN_WITHDRAW_CALLBACK)
+                    .on_admin_withdraw_balance(receiver_id, amount, token_id),
+            )
+    }
+
+    // Method to get the amount of a token owed to bettors, the default token if none is given
+    pub fn get_total_owed(&self, token_id: Option<AccountId>) -> U128 {
+        let token_id = self.internal_token_or_default(token_id);
+        U128(self.internal_ledger(&token_id).total_owed)
+    }
+
+    // Method to compare what the contract owes in a token with its balance of it.
+    // The balance has to be read from the FT contract, so this is a call that resolves
+    // to a BalanceInvariant rather than a view.
+    pub fn check_balance_invariant(&self, token_id: Option<AccountId>) -> Promise {
+        let token_id = self.internal_token_or_default(token_id);
+        ext_ft_contract::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+            .ft_balance_of(env::current_account_id())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_BALANCE_INVARIANT_CALLBACK)
+                    .on_balance_invariant(token_id),
+            )
+    }
+
+    #[private]
+    pub fn on_balance_invariant(&self, token_id: AccountId) -> BalanceInvariant {
+        let balance = Self::internal_read_balance();
+        // The ledger is kept up to date on every transfer, so nothing has to be summed up
+        let total_owed = self.internal_ledger(&token_id).total_owed;
+
+        BalanceInvariant {
+            token_id,
+            ft_balance: U128(balance),
+            total_owed: U128(total_owed),
+            holds: total_owed <= balance,
+        }
+    }
+
+    #[private]
+    pub fn on_admin_withdraw_balance(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        token_id: AccountId,
+    ) -> Promise {
+        let balance = Self::internal_read_balance();
+
+        let free_balance = balance.saturating_sub(self.internal_ledger(&token_id).total_owed);
+        require!(
+            amount.0 <= free_balance,
+            format!(
+                "Cannot withdraw {}, only {} is not owed to open challenges",
+                amount.0, free_balance
+            )
+        );
+
+        env::log_str(&format!(
+            "Admin withdrawal of {} {} to {}",
+            amount.0, token_id, receiver_id
+        ));
+
+        ext_ft_contract::ext(token_id)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(receiver_id, amount, None)
+    }
+
+    // Read the result of an ft_balance_of call
+    fn internal_read_balance() -> u128 {
+        match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<U128>(&value)
+                    .expect("Invalid ft_balance_of result")
+                    .0
+            }
+            PromiseResult::Failed => env::panic_str("Could not read the contract balance"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::{AccountId, PromiseResult};
+
+    use crate::test_utils::*;
+    use crate::ArenaProtocolContract;
+
+    fn stranger() -> AccountId {
+        "stranger.near".parse().unwrap()
+    }
+
+    // Alice and bob stake 500 each and alice claims the pool: 975 are paid out and
+    // 25 are accrued as protocol fees, which stay owed until withdrawn
+    fn contract_with_fees() -> ArenaProtocolContract {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 500, "");
+        vote(&mut contract, alice(), 1, vec![alice()]);
+        vote(&mut contract, bob(), 1, vec![alice()]);
+        context(alice(), 1);
+        assert_eq!(contract.claim(1), U128(975));
+        callback_context(PromiseResult::Successful(vec![]));
+        assert!(contract.external_call_callback(alice(), U128(975), 1));
+
+        assert_eq!(contract.get_total_owed(None), U128(25));
+        assert_eq!(contract.get_protocol_fees(None).available, U128(25));
+        contract
+    }
+
+    fn balance_of_arena(balance: u128) {
+        callback_context(PromiseResult::Successful(
+            near_sdk::serde_json::to_vec(&U128(balance)).unwrap(),
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot withdraw 1, only 0 is not owed to open challenges")]
+    fn admin_withdraw_cannot_take_accrued_fees() {
+        let mut contract = contract_with_fees();
+        balance_of_arena(25);
+        contract.on_admin_withdraw_balance(stranger(), U128(1), token());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot withdraw 101, only 100 is not owed to open challenges")]
+    fn admin_withdraw_cannot_take_owed_tokens() {
+        let mut contract = contract_with_fees();
+        // 100 more were sent with a plain ft_transfer
+        balance_of_arena(125);
+        contract.on_admin_withdraw_balance(stranger(), U128(101), token());
+    }
+
+    #[test]
+    fn admin_withdraw_can_take_tokens_nobody_is_owed() {
+        let mut contract = contract_with_fees();
+        balance_of_arena(125);
+        contract.on_admin_withdraw_balance(stranger(), U128(100), token());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot withdraw 1, only 0 is not owed to open challenges")]
+    fn admin_withdraw_counts_transfers_in_flight_as_owed() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 500, "");
+        vote(&mut contract, alice(), 1, vec![alice()]);
+        vote(&mut contract, bob(), 1, vec![alice()]);
+        context(alice(), 1);
+        contract.claim(1);
+
+        // The payout has left, but its callback has not run yet
+        balance_of_arena(25);
+        contract.on_admin_withdraw_balance(stranger(), U128(1), token());
+    }
+
+    #[test]
+    fn balance_invariant_compares_the_ledger_with_the_balance() {
+        let contract = contract_with_fees();
+        balance_of_arena(25);
+        let invariant = contract.on_balance_invariant(token());
+        assert_eq!(invariant.total_owed, U128(25));
+        assert!(invariant.holds);
+
+        balance_of_arena(24);
+        assert!(!contract.on_balance_invariant(token()).holds);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the protocol account can withdraw fees")]
+    fn stranger_cannot_withdraw_protocol_fees() {
+        let mut contract = contract_with_fees();
+        context(stranger(), 2);
+        contract.withdraw_protocol_fees(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot withdraw 26, only 25 is available")]
+    fn protocol_cannot_withdraw_more_than_its_fees() {
+        let mut contract = contract_with_fees();
+        context(protocol(), 2);
+        contract.withdraw_protocol_fees(Some(U128(26)), None);
+    }
+
+    #[test]
+    fn protocol_fees_stay_owed_until_the_transfer_succeeds() {
+        let mut contract = contract_with_fees();
+        context(protocol(), 2);
+        contract.withdraw_protocol_fees(None, None);
+        assert_eq!(contract.get_total_owed(None), U128(25));
+        assert_eq!(contract.get_protocol_fees(None).available, U128(0));
+
+        // A failed transfer makes the fees available again
+        callback_context(PromiseResult::Failed);
+        assert!(!contract.on_protocol_fee_transfer(U128(25), token()));
+        assert_eq!(contract.get_total_owed(None), U128(25));
+        assert_eq!(contract.get_protocol_fees(None).available, U128(25));
+
+        context(protocol(), 2);
+        contract.withdraw_protocol_fees(None, None);
+        callback_context(PromiseResult::Successful(vec![]));
+        assert!(contract.on_protocol_fee_transfer(U128(25), token()));
+        assert_eq!(contract.get_total_owed(None), U128(0));
+    }
+}
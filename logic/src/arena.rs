@@ -1,39 +1,32 @@
 use near_sdk::collections::UnorderedMap;
-use near_sdk::collections::{LookupMap, UnorderedSet, Vector};
-use near_sdk::env::current_account_id;
-use near_sdk::json_types::U128;
-use near_sdk::serde::Serialize;
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::{U128, U64};
 use near_sdk::PromiseOrValue;
-use near_sdk::{
-    env, log, near_bindgen, require, AccountId, BorshStorageKey, Gas, NearSchema, NearToken,
-    Promise,
-};
+use near_sdk::{env, log, near_bindgen, require, AccountId, Gas, NearToken, Promise};
 use std::collections::HashMap;
 
 use near_sdk::{ext_contract, PromiseResult};
-use near_sdk::{serde_json::json, GasWeight};
 
 use near_sdk::serde::Deserialize;
 use near_sdk::serde_json;
 
-pub const STORAGE_COST: NearToken = NearToken::from_millinear(1);
 pub const MIN_GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(5);
+// A failed payout puts the owed balance, the claim, the settlement and the portfolio back
+pub const GAS_FOR_PAYOUT_CALLBACK: Gas = Gas::from_tgas(30);
 
+use crate::challenge::{Challenge, ChallengeState, NewChallenge};
+use crate::events::ArenaEvent;
+use crate::voting;
 use crate::ArenaProtocolContract;
 use crate::ArenaProtocolContractExt;
 
-// Constants for the challenge states
-pub const STATE_PENDING: u8 = 1;
-pub const STATE_ONGOING: u8 = 2;
-pub const STATE_VOTING: u8 = 3;
-pub const STATE_VOTING_FINISH: u8 = 4;
-pub const STATE_CLAIM: u8 = 5;
-pub const STATE_CANCELLED: u8 = 5;
-
 /// FT contract
+// Only used through the generated `ext_ft_contract` module
+#[allow(dead_code)]
 #[ext_contract(ext_ft_contract)]
 trait ExtFTContract {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
     fn ft_transfer_call(
         &self,
         receiver_id: AccountId,
@@ -48,8 +41,8 @@ trait ExtFTContract {
 struct TokenReceiverMessage {
     action: String,
     participant: Option<AccountId>,
-    challenge_link: Option<String>, // New field for challenge link
-    challenge_id: Option<u32>,      // For PlaceBetOnly
+    challenge: Option<NewChallenge>, // Challenge to create for AddChallengeAndPlaceBet
+    challenge_id: Option<u32>,       // For PlaceBetOnly, AcceptChallenge and OpenDispute
 }
 
 #[near_bindgen]
@@ -57,40 +50,64 @@ impl ArenaProtocolContract {
     pub fn get_challenge_counter(&self) -> u32 {
         self.challenge_counter
     }
-    // Method to add a challenge with a unique ID and a link
-    pub fn add_challenge(&mut self, link: String) -> u32 {
-        let challenge_id = self.challenge_counter;
-        // self.challenges.insert(&challenge_id, &link);
+    // Method to add a challenge with a unique ID, a link and its participants
+    pub fn add_challenge(&mut self, challenge: NewChallenge) -> u32 {
+        self.internal_add_challenge(env::predecessor_account_id(), None, challenge, 0)
+    }
 
-        let state_string = Self::state_to_string(STATE_PENDING);
-        self.challenges.insert(&challenge_id, &state_string);
+    // Method for an invited participant to accept a challenge that needs no stake.
+    // Challenges with an acceptance stake are accepted with the AcceptChallenge action
+    // of ft_transfer_call instead.
+    pub fn accept_challenge(&mut self, challenge_id: u32) {
+        let challenge = self.internal_get_challenge(challenge_id);
+        require!(
+            challenge.acceptance_stake.0 == 0,
+            "This challenge needs a stake, accept it with ft_transfer_call"
+        );
+        self.internal_accept_challenge(challenge_id, env::predecessor_account_id());
+    }
 
-        self.challenge_counter += 1; // Increment the counter for the next challenge
-        challenge_id
+    // Method to get the state of a challenge by ID
+    pub fn get_challenge(&self, challenge_id: u32) -> Option<ChallengeState> {
+        self.challenges
+            .get(&challenge_id)
+            .map(|challenge| challenge.state)
     }
 
-    // Method to get challenge link by ID
-    pub fn get_challenge(&self, challenge_id: u32) -> Option<String> {
+    // Method to get the full challenge record by ID
+    pub fn get_challenge_details(&self, challenge_id: u32) -> Option<Challenge> {
         self.challenges.get(&challenge_id)
     }
-    // Method to place a bet on a participant in a challenge
-    pub fn place_bet(
+    // Record a bet on a participant in a challenge.
+    // Not callable directly: bets only come from ft_on_transfer, so the amount is always
+    // the amount of tokens the bettor actually deposited.
+    fn place_bet(
         &mut self,
         account: AccountId,
         challenge_id: u32,
         participant: AccountId,
         amount: u128,
     ) {
-        let current_state = self
-            .get_challenge(challenge_id)
-            .expect("Challenge does not exist");
-
-        // Ensure that bets cannot be placed if the state is Voting, Claim, or Cancelled
-        assert!(
-            current_state != Self::state_to_string(STATE_VOTING)
-                && current_state != Self::state_to_string(STATE_CLAIM)
-                && current_state != Self::state_to_string(STATE_CANCELLED),
-            "Cannot place a bet on a challenge because it is in voting, claim, or cancelled state"
+        let mut challenge = self.internal_get_challenge(challenge_id);
+
+        // Bets are only accepted before voting has started. While the challenge is
+        // Pending the only stakes are the creator's and the acceptance stakes.
+        Self::assert_challenge_state(
+            &challenge,
+            &[
+                ChallengeState::Pending,
+                ChallengeState::Accepted,
+                ChallengeState::Ongoing,
+            ],
+            "Cannot place a bet on a challenge once voting has started",
+        );
+        require!(
+            !Challenge::has_passed(challenge.betting_closes_at, env::block_timestamp()),
+            "Betting on this challenge has closed"
+        );
+        require!(
+            challenge.is_participant(&participant),
+            "Bets can only be placed on a participant of the challenge"
         );
         // Convert challenge_id to Vec<u8> using `to_le_bytes`
         let mut challenge_prefix = challenge_id.to_le_bytes().to_vec();
@@ -140,16 +157,26 @@ impl ArenaProtocolContract {
 
         // Insert the updated self_map back into the bets map for the challenge ID
         self.bets.insert(&challenge_id, &self_map);
+        self.internal_track_position(&account, challenge_id);
+        self.internal_add_owed(&challenge.token, amount);
 
-        self.update_set_challenge_state(challenge_id, STATE_ONGOING);
+        // Keep track of everyone that has been bet on, and how much
+        challenge.add_stake(&participant, amount);
+        challenge.updated_at = U64(env::block_timestamp());
+        self.challenges.insert(&challenge_id, &challenge);
 
-        log!(
-            "Bet placed successfully for challenge_id={}, account={}, participant={}, amount={}",
+        if challenge.state == ChallengeState::Accepted {
+            self.update_set_challenge_state(challenge_id, ChallengeState::Ongoing);
+        }
+
+        ArenaEvent::BetPlaced {
             challenge_id,
-            account,
+            account_id: account,
             participant,
-            new_bet_amount
-        );
+            amount: U128(amount),
+            total: U128(new_bet_amount),
+        }
+        .emit();
     }
     // Method to get the bet amount for a participant in a challenge
     pub fn get_bet_amount(
@@ -172,26 +199,46 @@ impl ArenaProtocolContract {
         msg: String,
     ) -> PromiseOrValue<U128> {
         let token_in = env::predecessor_account_id();
-        assert!(token_in == self.ft_contract, "The token is not supported");
 
         env::log_str(format!("Sender id: {:?}", sender_id).as_str());
         env::log_str(format!("Amount: {:?}", amount).as_str());
         env::log_str(format!("Message: {:?}", msg).as_str());
 
+        assert!(amount.0 > 0, "The amount should be a positive number");
+
         if msg.is_empty() {
+            // Nothing to bet on, hand the whole deposit back
             env::log_str(format!("Message is empty : {:?}", sender_id).as_str());
-            PromiseOrValue::Value(U128(0))
+            PromiseOrValue::Value(amount)
         } else {
             let message: TokenReceiverMessage =
                 serde_json::from_str(&msg).expect("WRONG_MSG_FORMAT");
 
+            // Stakes and bonds have to be in the token the challenge is played for
+            if let Some(challenge_id) = message.challenge_id {
+                if let Some(challenge) = self.challenges.get(&challenge_id) {
+                    require!(
+                        challenge.token == token_in,
+                        format!(
+                            "Challenge {} is played for {}",
+                            challenge_id, challenge.token
+                        )
+                    );
+                }
+            }
+
             match message.action.as_str() {
                 "AddChallengeAndPlaceBet" => {
                     // Handle case where both add_challenge and place_bet are called
                     if let Some(participant) = message.participant {
-                        if let Some(challenge_link) = message.challenge_link {
-                            // Add the challenge with the provided link
-                            let challenge_id = self.add_challenge(challenge_link);
+                        if let Some(challenge) = message.challenge {
+                            // Add the challenge with the provided link and participants
+                            let challenge_id = self.internal_add_challenge(
+                                sender_id.clone(),
+                                Some(token_in),
+                                challenge,
+                                amount.0,
+                            );
                             env::log_str(format!("challenge_id {:?}", challenge_id).as_str());
 
                             // Place the bet after adding the challenge
@@ -199,9 +246,7 @@ impl ArenaProtocolContract {
 
                             PromiseOrValue::Value(U128(0)) // Return success
                         } else {
-                            env::panic_str(
-                                "Challenge link is required for AddChallengeAndPlaceBet",
-                            );
+                            env::panic_str("challenge is required for AddChallengeAndPlaceBet");
                         }
                     } else {
                         env::panic_str("participant is None");
@@ -212,7 +257,12 @@ impl ArenaProtocolContract {
                     if let Some(participant) = message.participant {
                         if let Some(challenge_id) = message.challenge_id {
                             // Check if the challenge_id exists in self.challenges
-                            if self.challenges.get(&challenge_id).is_some() {
+                            if let Some(challenge) = self.challenges.get(&challenge_id) {
+                                require!(
+                                    challenge.state != ChallengeState::Pending,
+                                    "Bets open once every participant has accepted the challenge"
+                                );
+
                                 // Place the bet if the challenge exists
                                 self.place_bet(
                                     sender_id.clone(),
@@ -232,6 +282,39 @@ impl ArenaProtocolContract {
                         env::panic_str("participant is None");
                     }
                 }
+                "AcceptChallenge" => {
+                    // Handle an invited participant accepting with a matching stake
+                    if let Some(challenge_id) = message.challenge_id {
+                        let challenge = self.internal_get_challenge(challenge_id);
+                        let stake = challenge.acceptance_stake.0;
+                        require!(
+                            stake > 0,
+                            "This challenge needs no stake, accept it with accept_challenge"
+                        );
+                        require!(
+                            amount.0 >= stake,
+                            format!("Accepting this challenge needs a stake of {}", stake)
+                        );
+
+                        // The stake is a bet on the accepting participant itself
+                        self.place_bet(sender_id.clone(), challenge_id, sender_id.clone(), stake);
+                        self.internal_accept_challenge(challenge_id, sender_id);
+
+                        // Anything above the stake goes back to the sender
+                        PromiseOrValue::Value(U128(amount.0 - stake))
+                    } else {
+                        env::panic_str("challenge_id is required for AcceptChallenge");
+                    }
+                }
+                "OpenDispute" => {
+                    // Handle a participant contesting the result with a bond
+                    if let Some(challenge_id) = message.challenge_id {
+                        let unused = self.internal_open_dispute(sender_id, challenge_id, amount.0);
+                        PromiseOrValue::Value(U128(unused))
+                    } else {
+                        env::panic_str("challenge_id is required for OpenDispute");
+                    }
+                }
                 _ => env::panic_str("Unknown action"),
             }
         }
@@ -259,20 +342,43 @@ impl ArenaProtocolContract {
         total_bets
     }
 
-    // Method to update votes and positions based on the list of participants
-    // Method to update votes and positions based on the list of participants
+    // Method to vote on the result of a challenge. `participants` is the voter's ranking,
+    // best first, and may leave out participants, who then score no points.
     pub fn update_winner_by_challenge(&mut self, challenge_id: u32, participants: Vec<AccountId>) {
-        let account_id = env::signer_account_id();
-
-        // Ensure that the account has placed a bet in the challenge before allowing a vote
-        assert!(
-            self.bets
-                .get(&challenge_id)
-                .and_then(|self_map| self_map.get(&account_id))
-                .is_some(),
-            "Only accounts that placed a bet can vote"
+        let account_id = env::predecessor_account_id();
+        let mut challenge = self.internal_get_challenge(challenge_id);
+
+        // Votes are only accepted while the game is ongoing or already being voted on
+        Self::assert_challenge_state(
+            &challenge,
+            &[
+                ChallengeState::Accepted,
+                ChallengeState::Ongoing,
+                ChallengeState::Voting,
+            ],
+            "Cannot vote on a challenge in this state",
+        );
+        require!(
+            challenge.oracle.is_none(),
+            "This challenge is resolved by its oracle"
+        );
+        // The first vote closes betting, so it has to wait for the betting deadline
+        require!(
+            challenge.betting_closes_at.is_none()
+                || Challenge::has_passed(challenge.betting_closes_at, env::block_timestamp()),
+            "Voting on this challenge opens once betting has closed"
+        );
+        require!(
+            !Challenge::has_passed(challenge.voting_closes_at, env::block_timestamp()),
+            "Voting on this challenge has closed"
         );
 
+        // Votes can only name registered participants, each of them once
+        challenge.assert_valid_ranking(&participants);
+
+        // The voting policy of the challenge decides who votes, and with what weight
+        let (voter, weight) = self.internal_voter(&challenge, &account_id);
+
         // Prepare the prefix for vote tracking
         let mut vote_prefix = challenge_id.to_be_bytes().to_vec();
         vote_prefix.push(b's'); // Add a byte to identify votes
@@ -284,421 +390,429 @@ impl ArenaProtocolContract {
             .unwrap_or_else(|| UnorderedSet::new(vote_prefix));
 
         assert!(
-            !voted_accounts.contains(&account_id),
+            !voted_accounts.contains(&voter),
             "You have already voted for this challenge"
         );
 
         // Register the vote by the account
-        voted_accounts.insert(&account_id);
+        voted_accounts.insert(&voter);
         self.voted_accounts.insert(&challenge_id, &voted_accounts);
 
-        // Assign votes and positions based on the provided participant list
+        // Assign Borda points based on the position of each participant in the ranking
         let mut unique_prefix = challenge_id.to_be_bytes().to_vec();
         unique_prefix.push(b'z'); // Add a byte to identify winners
 
-        // Retrieve existing votes for this challenge, if any, to accumulate votes
+        // Retrieve existing points for this challenge, if any, to accumulate them
         let mut participant_map = self
             .winners
             .get(&challenge_id)
             .unwrap_or_else(|| UnorderedMap::new(unique_prefix));
 
-        // Increment vote counts for each participant based on their position
-        for participant in participants.iter() {
-            // Update the vote count by adding new vote to the existing count (if any)
-            let vote_count = participant_map.get(participant).unwrap_or(0) + 1;
-            participant_map.insert(participant, &vote_count);
+        let participant_count = challenge.participants.len();
+        for (position, participant) in participants.iter().enumerate() {
+            let points = participant_map
+                .get(participant)
+                .unwrap_or(0)
+                .saturating_add(
+                    voting::borda_points(participant_count, position).saturating_mul(weight),
+                );
+            participant_map.insert(participant, &points);
         }
 
-        // Store the updated participant map with accumulated votes
+        // Store the updated participant map with accumulated points
         self.winners.insert(&challenge_id, &participant_map);
 
-        self.update_set_challenge_state(challenge_id, STATE_VOTING);
+        // The first choice decides whether the voters agree on a winner
+        challenge.add_first_place_votes(&participants[0], weight);
+        challenge.voted_weight = U128(challenge.voted_weight.0.saturating_add(weight));
+
+        challenge.updated_at = U64(env::block_timestamp());
+        self.challenges.insert(&challenge_id, &challenge);
 
-        // After updating votes, update the positions if necessary
+        ArenaEvent::VoteCast {
+            challenge_id,
+            voter: voter.clone(),
+            ranking: participants.clone(),
+            weight: U128(weight),
+        }
+        .emit();
+
+        if matches!(
+            challenge.state,
+            ChallengeState::Accepted | ChallengeState::Ongoing
+        ) {
+            self.update_set_challenge_state(challenge_id, ChallengeState::Voting);
+        }
+
+        // Voting finishes as soon as every eligible voter has voted
+        if voted_accounts.len() >= self.internal_eligible_voter_count(&challenge) {
+            self.internal_finish_voting(challenge_id);
+        }
     }
 
-    // Method to get winners and their positions by challenge_id
+    // Method to get the position of every ranked participant by challenge_id
     pub fn get_winners_and_positions_by_challenge(
         &self,
         challenge_id: u32,
     ) -> Option<HashMap<AccountId, u8>> {
-        // Get the vote counts and positions of participants
-        let participant_map = self.winners.get(&challenge_id)?;
-
-        // Combine vote counts and positions into a result HashMap
-        let mut result = HashMap::new();
-        for (participant, vote) in participant_map.iter() {
-            result.insert(participant.clone(), (vote));
-        }
-        Some(result)
+        self.winners.get(&challenge_id)?;
+
+        let standings = self.internal_standings(&self.internal_get_challenge(challenge_id));
+        Some(
+            standings
+                .into_iter()
+                .map(|standing| (standing.participant, standing.position))
+                .collect(),
+        )
     }
 
-    pub fn calculate_winnings(&self, challenge_id: u32, rank: u8) -> Vec<(AccountId, u128)> {
-        let current_state = self
-            .get_challenge(challenge_id)
-            .expect("Challenge does not exist");
+    pub fn calculate_winnings(&self, challenge_id: u32) -> Vec<(AccountId, u128)> {
+        // Return the vector containing each account's winnings or losses (0 for losses)
+        self.internal_settlement_plan(challenge_id)
+            .payouts
+            .into_iter()
+            .map(|payout| (payout.account_id, payout.amount.0))
+            .collect()
+    }
 
-        // Ensure that bets cannot be placed if the state is Voting, Claim, or Cancelled
-        assert!(
-            current_state != Self::state_to_string(STATE_VOTING),
-            "Cannot place a bet on a challenge because claim, or cancelled state"
-        );
+    // Send winnings to a bettor. Internal only, the tokens stay in the owed balance while
+    // the transfer is in flight and only leave it once the callback sees it succeed.
+    pub(crate) fn transfer_token(
+        &mut self,
+        receiver_id: AccountId,
+        amount: u128,
+        challenge_id: u32,
+    ) -> Promise {
+        // Winnings are paid in the token the challenge was played for
+        let token = self.internal_get_challenge(challenge_id).token;
 
-        // Retrieve the vote counts for all participants in the challenge
-        let participant_votes: UnorderedMap<AccountId, u8> = self
-            .winners
-            .get(&challenge_id)
-            .expect("Winners for this challenge not found");
+        let promise = ext_ft_contract::ext(token)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(receiver_id.clone(), U128(amount), None);
 
-        // Sort participants by vote count in descending order
-        let mut sorted_participants: Vec<(AccountId, u8)> = participant_votes
-            .iter()
-            .map(|(k, v)| (k.clone(), v))
-            .collect();
-        sorted_participants.sort_by(|a, b| b.1.cmp(&a.1)); // Sort by votes (descending)
+        promise.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_PAYOUT_CALLBACK)
+                .external_call_callback(receiver_id, U128(amount), challenge_id),
+        )
+    }
 
-        // Initialize the list of winners based on rank
-        let mut winners: Vec<AccountId> = Vec::new();
+    #[private]
+    pub fn external_call_callback(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        challenge_id: u32,
+    ) -> bool {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                let token = self.internal_get_challenge(challenge_id).token;
+                self.internal_sub_owed(&token, amount.0);
+                ArenaEvent::PayoutSent {
+                    challenge_id,
+                    token_id: token,
+                    account_id: receiver_id,
+                    amount,
+                }
+                .emit();
+                true
+            }
+            PromiseResult::Failed => {
+                // The tokens never left, so they are still owed
+                let challenge = self.internal_get_challenge(challenge_id);
+
+                // Claims are over for a closed challenge, keep the payout as a refund instead
+                if challenge.state == ChallengeState::Closed {
+                    self.internal_add_pending_refund(&receiver_id, &challenge.token, amount.0);
+                    ArenaEvent::PayoutFailed {
+                        challenge_id,
+                        token_id: challenge.token,
+                        account_id: receiver_id,
+                        amount,
+                        retryable: false,
+                    }
+                    .emit();
+                    return false;
+                }
 
-        // Add participants to winners list based on rank or handle ties if votes are the same
-        if rank == 1 {
-            // Default case: Find the highest number of votes
-            let max_votes = sorted_participants[0].1; // Highest vote count
-            winners = sorted_participants
-                .iter()
-                .filter(|(_, votes)| *votes == max_votes) // Include participants with max votes
-                .map(|(participant, _)| participant.clone())
-                .collect();
-        } else {
-            // Handle ranks beyond 1 (top N participants)
-            winners = sorted_participants
-                .iter()
-                .take(rank as usize) // Take top N participants
-                .map(|(participant, _)| participant.clone())
-                .collect();
-        }
+                // Undo the claim so the winnings can be claimed again
+                self.internal_record_received(&receiver_id, challenge_id, amount.0, false);
+                if let Some(mut claimed) = self.claimed_accounts.get(&challenge_id) {
+                    claimed.remove(&receiver_id);
+                    self.claimed_accounts.insert(&challenge_id, &claimed);
+                }
+                if let Some(mut challenge) = self.challenges.get(&challenge_id) {
+                    if let Some(settlement) = challenge.settlement.as_mut() {
+                        let stake = self.internal_winning_stake(
+                            challenge_id,
+                            &settlement.winners(),
+                            &receiver_id,
+                        );
+                        settlement.total_paid =
+                            U128(settlement.total_paid.0.saturating_sub(amount.0));
+                        settlement.claimed_stake =
+                            U128(settlement.claimed_stake.0.saturating_sub(stake));
+                    }
+                    self.challenges.insert(&challenge_id, &challenge);
+                }
 
-        // Retrieve the nested map of bets for this challenge
-        let challenge_bets: UnorderedMap<AccountId, UnorderedMap<AccountId, u128>> = self
-            .bets
-            .get(&challenge_id)
-            .expect("Challenge bets not found");
-
-        // Initialize variables to store total bets and the pool
-        let mut total_bets_on_winner: u128 = 0;
-        let mut total_bets_on_loser: u128 = 0;
-        let mut total_pool: u128 = 0;
-
-        // Iterate over the accounts and their bets in the challenge
-        for (account, account_bets) in challenge_bets.iter() {
-            // Iterate over the participant bets for this account
-            for (participant, bet) in account_bets.iter() {
-                // let bet_amount = *bet;
-
-                // Check if the participant is one of the winners
-                if winners.contains(&participant) {
-                    // // If the participant is a winner, add to the winner's total
-                    total_bets_on_winner = total_bets_on_winner.saturating_add(bet);
-                } else {
-                    // Otherwise, add to the loser's total
-                    // total_bets_on_loser += bet_amount;
-                    total_bets_on_loser = total_bets_on_loser.saturating_add(bet);
+                ArenaEvent::PayoutFailed {
+                    challenge_id,
+                    token_id: challenge.token,
+                    account_id: receiver_id,
+                    amount,
+                    retryable: true,
                 }
-                // Add the bet to the total pool
-                total_pool = total_pool.saturating_add(bet);
+                .emit();
+                false
             }
         }
+    }
 
-        env::log_str(
-            format!(
-                "total_pool :  {:?} , total_bets_on_winner :  {:?} , total_bets_on_loser :  {:?}",
-                total_pool, total_bets_on_winner, total_bets_on_loser
-            )
-            .as_str(),
+    // `token_in` is the token the creator's stake was sent in, if there is a stake
+    fn internal_add_challenge(
+        &mut self,
+        creator: AccountId,
+        token_in: Option<AccountId>,
+        args: NewChallenge,
+        creator_stake: u128,
+    ) -> u32 {
+        let challenge_id = self.challenge_counter;
+
+        let token = args
+            .token
+            .clone()
+            .or_else(|| token_in.clone())
+            .unwrap_or_else(|| self.ft_contract.clone());
+        require!(
+            token_in.is_none_or(|token_in| token_in == token),
+            "The stake has to be sent in the token of the challenge"
         );
+        self.assert_supported_token(&token);
 
-        // Ensure there is a valid pool and bets on the losing side
-        assert!(total_pool > 0, "Invalid total pool");
-
-        // Formula to distribute winnings based on rank
-        // You can modify this part to change the distribution formula.
-        let distribute_winnings =
-            |bet_amount: u128, rank: u8, total_bets_on_winner: u128| -> u128 {
-                // Example formula: Winnings are scaled based on the rank
-                let scale_factor = match rank {
-                    1 => 1.0,  // 100% for first place
-                    2 => 0.75, // 75% for second place
-                    3 => 0.5,  // 50% for third place
-                    _ => 0.25, // 25% for others
-                };
-
-                // Regular calculation if there's no tie
-                ((total_pool * bet_amount) / total_bets_on_winner) as u128 * scale_factor as u128
-            };
-
-        // Create a vector to store the result for each supporter (winnings or losses)
-        let mut result_vec: Vec<(AccountId, u128)> = Vec::new();
-
-        // Calculate winnings for supporters on the winning side
-        for (account, account_bets) in challenge_bets.iter() {
-            let mut total_winnings_for_account: u128 = 0;
-
-            // Iterate over the participant bets for this account
-            for (participant, bet) in account_bets.iter() {
-                // let bet_amount = *bet;
-
-                // Check if the participant is one of the winners
-                if winners.contains(&participant) {
-                    // Calculate winnings using the formula based on rank
-                    let winnings = distribute_winnings(bet, rank, total_bets_on_winner);
-                    total_winnings_for_account =
-                        total_winnings_for_account.saturating_add(winnings);
-                }
-            }
+        let challenge = Challenge::new(
+            challenge_id,
+            creator,
+            token,
+            args,
+            creator_stake,
+            env::block_timestamp(),
+        );
+        self.assert_has_arbiter(&challenge);
+        self.challenges.insert(&challenge_id, &challenge);
+        self.internal_index_challenge(&challenge);
 
-            // Add the total winnings for this account (or 0 if no winnings)
-            if total_winnings_for_account > 0 {
-                result_vec.push((account.clone(), total_winnings_for_account));
-            } else {
-                result_vec.push((account.clone(), 0)); // No winnings for this account
-            }
+        ArenaEvent::ChallengeCreated {
+            challenge_id,
+            creator: challenge.creator.clone(),
+            token_id: challenge.token.clone(),
+            link: challenge.link.clone(),
+            format: challenge.format,
+            participants: challenge
+                .participants
+                .iter()
+                .map(|participant| participant.account_id.clone())
+                .collect(),
         }
+        .emit();
 
-        // Return the vector containing each account's winnings or losses (0 for losses)
-        result_vec
+        self.challenge_counter += 1; // Increment the counter for the next challenge
+        challenge_id
     }
 
-    #[payable]
-    pub fn claim_winnings(&mut self, challenge_id: u32, rank: u8) -> Vec<(AccountId, u128)> {
-        let current_state = self
-            .get_challenge(challenge_id)
-            .expect("Challenge does not exist");
+    // Mark an invited participant as accepted, the challenge goes live once everyone is in
+    fn internal_accept_challenge(&mut self, challenge_id: u32, account_id: AccountId) {
+        let mut challenge = self.internal_get_challenge(challenge_id);
 
-        // Ensure that bets cannot be placed if the state is Voting, Claim, or Cancelled
-        assert!(
-            current_state != Self::state_to_string(STATE_CLAIM)
-                && current_state != Self::state_to_string(STATE_CANCELLED),
-            "Cannot place a bet on a challenge because claim, or cancelled state"
+        Self::assert_challenge_state(
+            &challenge,
+            &[ChallengeState::Pending],
+            "Cannot accept a challenge that is no longer pending",
+        );
+        require!(
+            env::block_timestamp() < challenge.acceptance_deadline.0,
+            "The challenge can no longer be accepted"
         );
 
-        // Retrieve the vote counts for all participants in the challenge
-        let participant_votes: UnorderedMap<AccountId, u8> = self
-            .winners
-            .get(&challenge_id)
-            .expect("Winners for this challenge not found");
+        let participant = challenge
+            .participants
+            .iter_mut()
+            .find(|entry| entry.account_id == account_id)
+            .expect("Only invited participants can accept a challenge");
+        require!(!participant.accepted, "Challenge already accepted");
+        participant.accepted = true;
 
-        // Sort participants by vote count in descending order
-        let mut sorted_participants: Vec<(AccountId, u8)> = participant_votes
-            .iter()
-            .map(|(k, v)| (k.clone(), v))
-            .collect();
-        sorted_participants.sort_by(|a, b| b.1.cmp(&a.1)); // Sort by votes (descending)
+        challenge.updated_at = U64(env::block_timestamp());
+        self.challenges.insert(&challenge_id, &challenge);
 
-        // Initialize the list of winners based on rank
-        let mut winners: Vec<AccountId> = Vec::new();
+        ArenaEvent::ChallengeAccepted {
+            challenge_id,
+            account_id,
+        }
+        .emit();
 
-        // Add participants to winners list based on rank or handle ties if votes are the same
-        if rank == 1 {
-            // Default case: Find the highest number of votes
-            let max_votes = sorted_participants[0].1; // Highest vote count
-            winners = sorted_participants
-                .iter()
-                .filter(|(_, votes)| *votes == max_votes) // Include participants with max votes
-                .map(|(participant, _)| participant.clone())
-                .collect();
-        } else {
-            // Handle ranks beyond 1 (top N participants)
-            winners = sorted_participants
-                .iter()
-                .take(rank as usize) // Take top N participants
-                .map(|(participant, _)| participant.clone())
-                .collect();
+        if challenge.all_accepted() {
+            self.update_set_challenge_state(challenge_id, ChallengeState::Accepted);
         }
+    }
 
-        // Retrieve the nested map of bets for this challenge
-        let challenge_bets: UnorderedMap<AccountId, UnorderedMap<AccountId, u128>> = self
-            .bets
+    pub(crate) fn internal_get_challenge(&self, challenge_id: u32) -> Challenge {
+        self.challenges
             .get(&challenge_id)
-            .expect("Challenge bets not found");
-
-        // Initialize variables to store total bets and the pool
-        let mut total_bets_on_winner: u128 = 0;
-        let mut total_bets_on_loser: u128 = 0;
-        let mut total_pool: u128 = 0;
-
-        // Iterate over the accounts and their bets in the challenge
-        for (account, account_bets) in challenge_bets.iter() {
-            // Iterate over the participant bets for this account
-            for (participant, bet) in account_bets.iter() {
-                // let bet_amount = *bet;
-
-                // Check if the participant is one of the winners
-                if winners.contains(&participant) {
-                    // // If the participant is a winner, add to the winner's total
-                    total_bets_on_winner = total_bets_on_winner.saturating_add(bet);
-                } else {
-                    // Otherwise, add to the loser's total
-                    // total_bets_on_loser += bet_amount;
-                    total_bets_on_loser = total_bets_on_loser.saturating_add(bet);
-                }
-                // Add the bet to the total pool
-                total_pool = total_pool.saturating_add(bet);
-            }
-        }
+            .expect("Challenge does not exist")
+    }
 
-        env::log_str(
+    pub(crate) fn assert_challenge_state(
+        challenge: &Challenge,
+        allowed: &[ChallengeState],
+        message: &str,
+    ) {
+        require!(
+            allowed.contains(&challenge.state),
             format!(
-                "total_pool :  {:?} , total_bets_on_winner :  {:?} , total_bets_on_loser :  {:?}",
-                total_pool, total_bets_on_winner, total_bets_on_loser
+                "{} (challenge {} is {:?})",
+                message, challenge.id, challenge.state
             )
-            .as_str(),
         );
+    }
 
-        // Ensure there is a valid pool and bets on the losing side
-        assert!(total_pool > 0, "Invalid total pool");
-
-        // Formula to distribute winnings based on rank
-        // You can modify this part to change the distribution formula.
-        let distribute_winnings =
-            |bet_amount: u128, rank: u8, total_bets_on_winner: u128| -> u128 {
-                // Example formula: Winnings are scaled based on the rank
-                let scale_factor = match rank {
-                    1 => 1.0,  // 100% for first place
-                    2 => 0.75, // 75% for second place
-                    3 => 0.5,  // 50% for third place
-                    _ => 0.25, // 25% for others
-                };
-
-                // Regular calculation if there's no tie
-                ((total_pool * bet_amount) / total_bets_on_winner) as u128 * scale_factor as u128
-            };
-
-        // Create a vector to store the result for each supporter (winnings or losses)
-        let mut result_vec: Vec<(AccountId, u128)> = Vec::new();
-
-        // Calculate winnings for supporters on the winning side
-        for (account, account_bets) in challenge_bets.iter() {
-            let mut total_winnings_for_account: u128 = 0;
-
-            // Iterate over the participant bets for this account
-            for (participant, bet) in account_bets.iter() {
-                // let bet_amount = *bet;
-
-                // Check if the participant is one of the winners
-                if winners.contains(&participant) {
-                    // Calculate winnings using the formula based on rank
-                    let winnings = distribute_winnings(bet, rank, total_bets_on_winner);
-                    total_winnings_for_account =
-                        total_winnings_for_account.saturating_add(winnings);
-                }
-            }
+    // Move a challenge to its next state, rejecting moves the state machine does not allow
+    pub(crate) fn update_set_challenge_state(
+        &mut self,
+        challenge_id: u32,
+        new_state: ChallengeState,
+    ) {
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        let prev_state = challenge.state;
 
-            // Add the total winnings for this account (or 0 if no winnings)
-            if total_winnings_for_account > 0 {
-                result_vec.push((account.clone(), total_winnings_for_account));
-            } else {
-                result_vec.push((account.clone(), 0)); // No winnings for this account
-            }
-        }
+        require!(
+            prev_state.can_transition_to(new_state),
+            format!(
+                "Invalid state transition for challenge {} from {:?} to {:?}",
+                challenge_id, prev_state, new_state
+            )
+        );
 
-        for (account_id, amount) in result_vec.iter() {
-            if *amount > 0 {
-                self.transfer_token(account_id.clone(), *amount);
-            }
+        // Update the state of the challenge to the new state
+        challenge.state = new_state;
+        challenge.updated_at = U64(env::block_timestamp());
+        self.challenges.insert(&challenge_id, &challenge);
+        self.internal_index_state(challenge_id, Some(prev_state), new_state);
+
+        ArenaEvent::StateChanged {
+            challenge_id,
+            from: prev_state,
+            to: new_state,
         }
+        .emit();
+    }
+}
 
-        self.update_set_challenge_state(challenge_id, STATE_CLAIM);
-        // Return the vector containing each account's winnings or losses (0 for losses)
-        result_vec
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::PromiseOrValue;
+
+    use crate::challenge::{ChallengeState, DEFAULT_ACCEPTANCE_PERIOD};
+    use crate::test_utils::*;
+
+    fn refunded(result: PromiseOrValue<U128>) -> u128 {
+        match result {
+            PromiseOrValue::Value(unused) => unused.0,
+            PromiseOrValue::Promise(_) => panic!("Expected an amount to send back"),
+        }
     }
 
-    #[payable]
-    pub fn transfer_token(&mut self, receiver_id: AccountId, amount: u128) -> Promise {
-        let promise = ext_ft_contract::ext(self.ft_contract.clone())
-            .with_attached_deposit(NearToken::from_yoctonear(1))
-            .ft_transfer(receiver_id, near_sdk::json_types::U128(amount), None);
+    #[test]
+    fn creation_registers_the_participants() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob(), charlie()], 100, "");
 
-        return promise.then(
-            Self::ext(env::current_account_id())
-                .with_static_gas(MIN_GAS_FOR_FT_TRANSFER)
-                .external_call_callback(),
+        let challenge = contract.get_challenge_details(1).unwrap();
+        let registered: Vec<_> = challenge
+            .participants
+            .iter()
+            .map(|participant| (participant.account_id.clone(), participant.total_staked))
+            .collect();
+        assert_eq!(
+            registered,
+            vec![(alice(), U128(100)), (bob(), U128(0)), (charlie(), U128(0))]
         );
     }
 
-    #[private]
-    pub fn external_call_callback(&self) -> bool {
-        // handle the callback logic here
-        true
+    #[test]
+    #[should_panic(expected = "is listed twice")]
+    fn participants_cannot_be_listed_twice() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob(), alice()], 100, "");
     }
 
-    // Helper function to convert u8 state to string
-    fn state_to_string(state: u8) -> String {
-        match state {
-            STATE_PENDING => "1".to_string(),
-            STATE_ONGOING => "2".to_string(),
-            STATE_VOTING => "3".to_string(),
-            STATE_CLAIM => "4".to_string(),
-            STATE_CANCELLED => "5".to_string(),
-            _ => "6".to_string(), // Default case for invalid state
-        }
+    #[test]
+    #[should_panic(expected = "Bets can only be placed on a participant of the challenge")]
+    fn bets_only_back_participants() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, dave(), 1, charlie(), 100);
     }
 
-    fn update_set_challenge_state(&mut self, challenge_id: u32, new_state: u8) {
-        // Check if the challenge exists
-        let current_state = self.challenges.get(&challenge_id);
-        if current_state.is_none() {
-            env::log_str(&format!("Challenge {} does not exist", challenge_id));
-            return;
-        }
+    #[test]
+    #[should_panic(expected = "is not a participant of this challenge")]
+    fn votes_only_rank_participants() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        vote(&mut contract, alice(), 1, vec![alice(), charlie()]);
+    }
 
-        // Validate the new state value (must be between 1 and 5)
-        assert!(new_state >= 1 && new_state <= 5, "Invalid state value");
+    #[test]
+    fn challenge_goes_live_once_everyone_matched_the_stake() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob(), charlie()], 100, "");
 
-        // Retrieve the previous state as a string
-        let prev_state = current_state.unwrap();
+        accept(&mut contract, bob(), 1, 100);
+        assert_eq!(contract.get_challenge(1), Some(ChallengeState::Pending));
 
-        // Update the state of the challenge to the new state
-        let new_state_string = Self::state_to_string(new_state);
-        self.challenges.insert(&challenge_id, &new_state_string);
-
-        // Log the previous and new states
-        env::log_str(&format!(
-            "Challenge {} state updated from {} to {}",
-            challenge_id, prev_state, new_state_string
-        ));
+        // Anything above the acceptance stake goes back to the sender
+        let msg = r#"{"action":"AcceptChallenge","challenge_id":1}"#.to_string();
+        assert_eq!(refunded(transfer(&mut contract, charlie(), 150, msg)), 50);
+        assert_eq!(contract.get_challenge(1), Some(ChallengeState::Accepted));
+        assert_eq!(contract.get_challenge_details(1).unwrap().total_pool(), 300);
     }
 
-    // Optionally, convert state string back to u8 (if needed in future logic)
-    fn get_state(state: &str) -> Option<u8> {
-        match state {
-            "pending" => Some(STATE_PENDING),
-            "ongoing" => Some(STATE_ONGOING),
-            "voting" => Some(STATE_VOTING),
-            "voting finish" => Some(STATE_VOTING_FINISH),
-            "claim" => Some(STATE_CLAIM),
-            "cancelled" => Some(STATE_CANCELLED),
-            _ => None, // Unknown state
-        }
+    #[test]
+    #[should_panic(expected = "Accepting this challenge needs a stake of 100")]
+    fn acceptance_stake_has_to_be_matched() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        accept(&mut contract, bob(), 1, 99);
     }
 
-    pub fn check_bet_and_vote_count(&mut self, challenge_id: u32) {
-        let mut voted_accounts = self
-            .voted_accounts
-            .get(&challenge_id)
-            .unwrap_or_else(|| UnorderedSet::new(b'e'));
+    #[test]
+    #[should_panic(expected = "This challenge needs a stake, accept it with ft_transfer_call")]
+    fn staked_challenge_cannot_be_accepted_for_free() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        context(bob(), 0);
+        contract.accept_challenge(1);
+    }
 
-        // Get the length of the voted_accounts
-        let voted_accounts_len = voted_accounts.len();
+    #[test]
+    #[should_panic(expected = "The challenge can no longer be accepted")]
+    fn acceptance_closes_at_the_deadline() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        context(bob(), DEFAULT_ACCEPTANCE_PERIOD);
+        accept(&mut contract, bob(), 1, 100);
+    }
 
-        let bets_length = self
-            .bets
-            .get(&challenge_id)
-            .map_or(0, |self_map| self_map.len());
+    #[test]
+    #[should_panic(expected = "Only invited participants can accept a challenge")]
+    fn only_invited_participants_can_accept() {
+        let mut contract = contract();
+        let challenge = challenge_json(&[alice(), bob()], "");
+        context(alice(), 0);
+        contract.add_challenge(near_sdk::serde_json::from_str(&challenge).unwrap());
 
-        // Check if the lengths are the same and update the challenge state if they are
-        if bets_length == voted_accounts_len {
-            self.update_set_challenge_state(challenge_id, STATE_VOTING_FINISH);
-        }
+        context(charlie(), 0);
+        contract.accept_challenge(1);
     }
 }
-
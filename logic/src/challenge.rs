@@ -0,0 +1,690 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{near, require, AccountId};
+
+use crate::payout;
+
+// Time the invited participants have to accept a challenge, unless the creator sets another
+pub const DEFAULT_ACCEPTANCE_PERIOD: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+// Time winners have at least to claim once claims open, however late voting finishes
+pub const MIN_CLAIM_WINDOW: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+
+// Lifecycle state of a challenge
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChallengeState {
+    // Waiting for the invited participants to accept
+    Pending,
+    // Every participant is in, the challenge is live
+    Accepted,
+    Ongoing,
+    Voting,
+    VotingFinished,
+    // A participant contested the result, waiting for the arbiter
+    Disputed,
+    Claim,
+    Cancelled,
+    // The claim window has passed, unclaimed winnings were moved to pending refunds
+    Closed,
+}
+
+impl ChallengeState {
+    // Convert the state strings stored by the first contract version.
+    // Those were written by `state_to_string`, which mapped STATE_CLAIM to "4"
+    // and let STATE_VOTING_FINISH fall through to "6".
+    pub fn from_legacy(state: &str) -> Option<Self> {
+        match state {
+            "1" => Some(Self::Pending),
+            "2" => Some(Self::Ongoing),
+            "3" => Some(Self::Voting),
+            "4" => Some(Self::Claim),
+            "5" => Some(Self::Cancelled),
+            "6" => Some(Self::VotingFinished),
+            _ => None,
+        }
+    }
+
+    // The transition table of the challenge state machine.
+    // Every state change goes through here, so a challenge can never move
+    // backwards or skip a phase.
+    pub fn can_transition_to(&self, next: ChallengeState) -> bool {
+        use ChallengeState::*;
+
+        matches!(
+            (self, next),
+            (Pending, Accepted)
+                | (Accepted, Ongoing)
+                | (Accepted, Voting)
+                | (Ongoing, Voting)
+                | (Voting, VotingFinished)
+                | (VotingFinished, Claim)
+                | (VotingFinished, Disputed)
+                | (Disputed, VotingFinished)
+                | (Claim, Closed)
+                // Replay after the voters failed to agree
+                | (VotingFinished, Ongoing)
+                | (Pending, Cancelled)
+                | (Accepted, Cancelled)
+                | (Ongoing, Cancelled)
+                | (Voting, Cancelled)
+                | (VotingFinished, Cancelled)
+                // The arbiter did not rule in time
+                | (Disputed, Cancelled)
+        )
+    }
+}
+
+// The part of the pool paid to the backers of one participant, by its final position
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacePayout {
+    pub participant: AccountId,
+    pub position: u8,
+    // Shared pro rata by everyone that bet on the participant
+    pub amount: U128,
+}
+
+// How the pool was split once the challenge has been claimed
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettlementInfo {
+    // Paid places, from the payout schedule applied to the final standings
+    pub places: Vec<PlacePayout>,
+    pub total_pool: U128,
+    // Protocol fee taken out of the pool before it is shared
+    pub fee: U128,
+    pub total_paid: U128,
+    // Winning stake of the accounts that have been paid, used to find the last claimant
+    pub claimed_stake: U128,
+    pub settled_at: U64,
+}
+
+impl SettlementInfo {
+    // Participants whose backers are paid
+    pub fn winners(&self) -> Vec<AccountId> {
+        self.places
+            .iter()
+            .map(|place| place.participant.clone())
+            .collect()
+    }
+}
+
+// How the participants of a challenge compete
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChallengeFormat {
+    // Exactly two players
+    OneVsOne,
+    // Two or more players, each on their own
+    MultiPlayer,
+    // Two or more teams, each with one or more members
+    Teams,
+}
+
+// Who decides the result of a challenge
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VotingPolicy {
+    // Every participant casts one vote for its side, a team through any of its members
+    #[default]
+    ParticipantsOnly,
+    // Every bettor votes, weighted by the total they have staked on the challenge
+    StakeWeighted,
+    // Every bettor casts one vote whatever their stake, as in the first contract version
+    OneVotePerBettor,
+}
+
+// Share of the vote weight the winner needs for the result to stand
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ConsensusRule {
+    // Every vote names the same winner
+    #[default]
+    Unanimous,
+    // More than half of the vote weight
+    Majority,
+    // At least two thirds of the vote weight
+    Supermajority,
+}
+
+impl ConsensusRule {
+    pub fn is_met(&self, winner_weight: u128, total_weight: u128) -> bool {
+        if total_weight == 0 {
+            return false;
+        }
+        let (winner, total) = (
+            payout::U256::from(winner_weight),
+            payout::U256::from(total_weight),
+        );
+        match self {
+            ConsensusRule::Unanimous => winner == total,
+            ConsensusRule::Majority => winner * 2 > total,
+            ConsensusRule::Supermajority => winner * 3 >= total * 2,
+        }
+    }
+}
+
+// Result of a finished vote
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub enum VotingOutcome {
+    Decided(AccountId),
+    // The voters did not agree, the stakes stay until a rematch or a cancel
+    NoConsensus,
+}
+
+// How the result of a challenge can be contested once voting has finished
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisputeTerms {
+    // Nanoseconds after voting finishes during which a participant can dispute
+    pub period: U64,
+    // Deposit that comes with a dispute, forfeited if the arbiter upholds the result
+    pub bond: U128,
+    // Account that resolves disputes, any protocol arbiter if unset
+    pub arbiter: Option<AccountId>,
+}
+
+// An open or resolved dispute
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dispute {
+    pub disputer: AccountId,
+    pub bond: U128,
+    pub opened_at: U64,
+    // Set once the arbiter has ruled
+    pub resolved_at: Option<U64>,
+}
+
+// A participant of a challenge: a player, or a team identified by its captain.
+// Bets and votes name the participant's account_id.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Participant {
+    pub account_id: AccountId,
+    // Everyone playing for this participant, just the account itself for a single player
+    pub members: Vec<AccountId>,
+    // Invited participants have to accept before they are in
+    pub accepted: bool,
+    // Total amount bet on this participant
+    pub total_staked: U128,
+    // Vote weight of the ballots that ranked this participant first
+    pub first_place_votes: U128,
+}
+
+// A participant as declared when creating a challenge
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct NewParticipant {
+    pub account_id: AccountId,
+    // Team members, only for the Teams format. Defaults to the account itself.
+    pub members: Option<Vec<AccountId>>,
+}
+
+// Arguments to create a challenge
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct NewChallenge {
+    // Link or title of the game / event the challenge is about
+    pub link: String,
+    pub format: ChallengeFormat,
+    pub participants: Vec<NewParticipant>,
+    // Token the challenge is played for. Defaults to the token the creator's stake was sent
+    // in, or to the contract's default token for challenges created without a stake.
+    pub token: Option<AccountId>,
+    // Stake every invited participant deposits to accept. Defaults to the creator's stake.
+    pub acceptance_stake: Option<U128>,
+    // Nanoseconds the invited participants have to accept, DEFAULT_ACCEPTANCE_PERIOD if unset
+    pub acceptance_period: Option<U64>,
+    // Defaults to ParticipantsOnly
+    pub voting_policy: Option<VotingPolicy>,
+    // Defaults to Unanimous
+    pub consensus_rule: Option<ConsensusRule>,
+    // Share of the pool for each place in basis points, e.g. [7000, 2000, 1000].
+    // Defaults to everything for the winner.
+    pub payout_schedule: Option<Vec<u32>>,
+    // Phase deadlines as block timestamps in nanoseconds, each phase is open-ended if unset
+    pub betting_closes_at: Option<U64>,
+    pub voting_closes_at: Option<U64>,
+    pub claim_expires_at: Option<U64>,
+    // Results can only be disputed if set
+    pub dispute_terms: Option<DisputeTerms>,
+    // Account that reports the result instead of a vote, e.g. for sports and event wagers
+    pub oracle: Option<AccountId>,
+}
+
+// A challenge record, stored per challenge id
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct Challenge {
+    pub id: u32,
+    pub creator: AccountId,
+    // Link or title of the game / event the challenge is about
+    pub link: String,
+    pub format: ChallengeFormat,
+    // NEP-141 token every stake, bond and payout of the challenge is in
+    pub token: AccountId,
+    // Registered participants, in the order they were declared
+    pub participants: Vec<Participant>,
+    // Stake an invited participant deposits with AcceptChallenge, 0 to accept for free
+    pub acceptance_stake: U128,
+    // Once passed, a challenge that is still Pending can be cancelled by anyone
+    pub acceptance_deadline: U64,
+    pub voting_policy: VotingPolicy,
+    pub consensus_rule: ConsensusRule,
+    // Share of the pool for each place in basis points, adding up to 100%
+    pub payout_schedule: Vec<u32>,
+    // No bets, including acceptance stakes, from this block timestamp on
+    pub betting_closes_at: Option<U64>,
+    // Voting finishes with the votes cast so far from this block timestamp on
+    pub voting_closes_at: Option<U64>,
+    // Winnings that are not claimed by then are kept as pending refunds of their winners
+    pub claim_expires_at: Option<U64>,
+    pub dispute_terms: Option<DisputeTerms>,
+    // End of the dispute window, set when voting finishes. Nothing is paid before then.
+    pub dispute_deadline: Option<U64>,
+    pub dispute: Option<Dispute>,
+    // Only this account reports the result if set, nobody votes
+    pub oracle: Option<AccountId>,
+    // Vote weight cast so far, what the consensus rule measures the first places against
+    pub voted_weight: U128,
+    // Set once voting has finished, cleared again by a rematch
+    pub outcome: Option<VotingOutcome>,
+    pub state: ChallengeState,
+    // Block timestamps in nanoseconds, `updated_at` moves on every bet, vote and state change
+    pub created_at: U64,
+    pub updated_at: U64,
+    pub settlement: Option<SettlementInfo>,
+}
+
+impl Challenge {
+    // `creator_stake` is what the creator deposited along with the challenge, if anything
+    pub fn new(
+        id: u32,
+        creator: AccountId,
+        token: AccountId,
+        args: NewChallenge,
+        creator_stake: u128,
+        now: u64,
+    ) -> Self {
+        let acceptance_period = args
+            .acceptance_period
+            .map_or(DEFAULT_ACCEPTANCE_PERIOD, |period| period.0);
+        // Accepting means staking, which ends when betting closes
+        let acceptance_deadline = args
+            .betting_closes_at
+            .map_or(now.saturating_add(acceptance_period), |closes_at| {
+                now.saturating_add(acceptance_period).min(closes_at.0)
+            });
+        let mut challenge = Self {
+            id,
+            creator,
+            link: args.link,
+            format: args.format,
+            token,
+            participants: Vec::new(),
+            acceptance_stake: args.acceptance_stake.unwrap_or(U128(creator_stake)),
+            acceptance_deadline: U64(acceptance_deadline),
+            voting_policy: args.voting_policy.unwrap_or_default(),
+            consensus_rule: args.consensus_rule.unwrap_or_default(),
+            payout_schedule: args
+                .payout_schedule
+                .unwrap_or_else(|| vec![payout::BASIS_POINTS]),
+            betting_closes_at: args.betting_closes_at,
+            voting_closes_at: args.voting_closes_at,
+            claim_expires_at: args.claim_expires_at,
+            dispute_terms: args.dispute_terms,
+            dispute_deadline: None,
+            dispute: None,
+            oracle: args.oracle,
+            voted_weight: U128(0),
+            outcome: None,
+            state: ChallengeState::Pending,
+            created_at: U64(now),
+            updated_at: U64(now),
+            settlement: None,
+        };
+
+        for entry in args.participants {
+            let members = entry
+                .members
+                .unwrap_or_else(|| vec![entry.account_id.clone()]);
+            // The creator's own side does not have to accept
+            let accepted =
+                entry.account_id == challenge.creator || members.contains(&challenge.creator);
+            challenge.add_participant(entry.account_id, members, accepted);
+        }
+        challenge.assert_valid_participants();
+        payout::assert_valid_schedule(&challenge.payout_schedule);
+        require!(
+            challenge.payout_schedule.len() <= challenge.participants.len(),
+            "The payout schedule has more places than the challenge has participants"
+        );
+        challenge.assert_valid_deadlines(now);
+        if let Some(terms) = &challenge.dispute_terms {
+            require!(
+                terms.period.0 > 0 && terms.bond.0 > 0,
+                "A dispute period and bond have to be positive"
+            );
+        }
+        challenge
+    }
+
+    // Panics unless the deadlines that are set lie in the future and in phase order
+    fn assert_valid_deadlines(&self, now: u64) {
+        let mut previous = now;
+        for deadline in [
+            self.betting_closes_at,
+            self.voting_closes_at,
+            self.claim_expires_at,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            require!(
+                deadline.0 > previous,
+                "Deadlines have to be in the future and in the order betting, voting, claim"
+            );
+            previous = deadline.0;
+        }
+
+        // Claims only open once the dispute period after voting is over, and then have
+        // to stay open for a whole claim window
+        if let Some(claim_expires_at) = self.claim_expires_at {
+            let voting_closes_at = self
+                .voting_closes_at
+                .or(self.betting_closes_at)
+                .map_or(now, |deadline| deadline.0);
+            let dispute_period = self
+                .dispute_terms
+                .as_ref()
+                .map_or(0, |terms| terms.period.0);
+            require!(
+                claim_expires_at.0
+                    >= voting_closes_at
+                        .saturating_add(dispute_period)
+                        .saturating_add(self.claim_window()),
+                "The claim window has to outlast the dispute period after voting closes"
+            );
+        }
+    }
+
+    // How long claims stay open at least: MIN_CLAIM_WINDOW, or one dispute period if longer
+    pub fn claim_window(&self) -> u64 {
+        self.dispute_terms
+            .as_ref()
+            .map_or(MIN_CLAIM_WINDOW, |terms| {
+                terms.period.0.max(MIN_CLAIM_WINDOW)
+            })
+    }
+
+    // Deadlines of a replayed challenge. The old ones have usually passed, so each phase
+    // gets the given deadline or none at all, and the dispute window starts over.
+    pub fn reschedule(
+        &mut self,
+        betting_closes_at: Option<U64>,
+        voting_closes_at: Option<U64>,
+        claim_expires_at: Option<U64>,
+        now: u64,
+    ) {
+        self.betting_closes_at = betting_closes_at;
+        self.voting_closes_at = voting_closes_at;
+        self.claim_expires_at = claim_expires_at;
+        self.dispute_deadline = None;
+        self.assert_valid_deadlines(now);
+    }
+
+    // Claims open at `claims_open_at`, once nothing can be disputed any more, and stay
+    // open for at least a claim window, so a late vote or ruling never closes the claims
+    // before the winners could claim.
+    pub fn keep_claims_open(&mut self, claims_open_at: u64) {
+        if let Some(claim_expires_at) = self.claim_expires_at {
+            let earliest = claims_open_at.saturating_add(self.claim_window());
+            self.claim_expires_at = Some(U64(claim_expires_at.0.max(earliest)));
+        }
+    }
+
+    // Whether the betting and voting deadlines and the dispute window that are set have
+    // all passed, true for a challenge without any
+    pub fn phase_deadlines_passed(&self, now: u64) -> bool {
+        [
+            self.betting_closes_at,
+            self.voting_closes_at,
+            self.dispute_deadline,
+        ]
+        .into_iter()
+        .flatten()
+        .all(|deadline| now >= deadline.0)
+    }
+
+    // Whether a deadline is set and has passed
+    pub fn has_passed(deadline: Option<U64>, now: u64) -> bool {
+        deadline.is_some_and(|deadline| now >= deadline.0)
+    }
+
+    pub fn add_participant(
+        &mut self,
+        account_id: AccountId,
+        members: Vec<AccountId>,
+        accepted: bool,
+    ) {
+        self.participants.push(Participant {
+            account_id,
+            members,
+            accepted,
+            total_staked: U128(0),
+            first_place_votes: U128(0),
+        });
+    }
+
+    pub fn participant(&self, account_id: &AccountId) -> Option<&Participant> {
+        self.participants
+            .iter()
+            .find(|entry| entry.account_id == *account_id)
+    }
+
+    pub fn is_participant(&self, account_id: &AccountId) -> bool {
+        self.participant(account_id).is_some()
+    }
+
+    // The participant an account plays for, as the participant itself or a team member
+    pub fn side_of(&self, account_id: &AccountId) -> Option<&Participant> {
+        self.participants
+            .iter()
+            .find(|entry| entry.account_id == *account_id || entry.members.contains(account_id))
+    }
+
+    pub fn all_accepted(&self) -> bool {
+        self.participants.iter().all(|entry| entry.accepted)
+    }
+
+    // Panics unless the participant list fits the challenge format
+    fn assert_valid_participants(&self) {
+        match self.format {
+            ChallengeFormat::OneVsOne => require!(
+                self.participants.len() == 2,
+                "A one versus one challenge needs exactly two participants"
+            ),
+            ChallengeFormat::MultiPlayer | ChallengeFormat::Teams => require!(
+                self.participants.len() >= 2,
+                "A challenge needs at least two participants"
+            ),
+        }
+
+        let mut seen: Vec<&AccountId> = Vec::new();
+        for entry in self.participants.iter() {
+            require!(
+                !seen.contains(&&entry.account_id),
+                format!("Participant {} is listed twice", entry.account_id)
+            );
+            seen.push(&entry.account_id);
+
+            if self.format == ChallengeFormat::Teams {
+                require!(
+                    !entry.members.is_empty(),
+                    format!("Team {} has no members", entry.account_id)
+                );
+            } else {
+                require!(
+                    entry.members == [entry.account_id.clone()],
+                    "Only teams can have members"
+                );
+            }
+        }
+
+        // Nobody can play on two sides
+        let mut members: Vec<&AccountId> = Vec::new();
+        for member in self
+            .participants
+            .iter()
+            .flat_map(|entry| entry.members.iter())
+        {
+            require!(
+                !members.contains(&member),
+                format!("{} is a member of more than one team", member)
+            );
+            members.push(member);
+        }
+    }
+
+    // Add a bet to the running total of a registered participant
+    pub fn add_stake(&mut self, participant: &AccountId, amount: u128) {
+        let entry = self
+            .participants
+            .iter_mut()
+            .find(|entry| entry.account_id == *participant)
+            .unwrap_or_else(|| {
+                near_sdk::env::panic_str(&format!(
+                    "{} is not a participant of this challenge",
+                    participant
+                ))
+            });
+        entry.total_staked = U128(entry.total_staked.0.saturating_add(amount));
+    }
+
+    // Panics unless the ranking names registered participants, each of them once
+    pub fn assert_valid_ranking(&self, ranking: &[AccountId]) {
+        require!(
+            !ranking.is_empty(),
+            "A ranking has to name at least one participant"
+        );
+        for (index, participant) in ranking.iter().enumerate() {
+            require!(
+                self.is_participant(participant),
+                format!("{} is not a participant of this challenge", participant)
+            );
+            require!(
+                !ranking[..index].contains(participant),
+                format!("{} is listed more than once", participant)
+            );
+        }
+    }
+
+    // Count a ballot that ranked the participant first
+    pub fn add_first_place_votes(&mut self, participant: &AccountId, weight: u128) {
+        if let Some(entry) = self
+            .participants
+            .iter_mut()
+            .find(|entry| entry.account_id == *participant)
+        {
+            entry.first_place_votes = U128(entry.first_place_votes.0.saturating_add(weight));
+        }
+    }
+
+    // Sum of every bet placed on the challenge
+    pub fn total_pool(&self) -> u128 {
+        self.participants.iter().fold(0u128, |total, entry| {
+            total.saturating_add(entry.total_staked.0)
+        })
+    }
+
+    // Sum of the bets placed on the given participants
+    pub fn total_staked_on(&self, participants: &[AccountId]) -> u128 {
+        self.participants
+            .iter()
+            .filter(|entry| participants.contains(&entry.account_id))
+            .fold(0u128, |total, entry| {
+                total.saturating_add(entry.total_staked.0)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChallengeState::{self, *};
+
+    const ALL_STATES: [ChallengeState; 9] = [
+        Pending,
+        Accepted,
+        Ongoing,
+        Voting,
+        VotingFinished,
+        Disputed,
+        Claim,
+        Cancelled,
+        Closed,
+    ];
+
+    const LEGAL_TRANSITIONS: [(ChallengeState, ChallengeState); 16] = [
+        (Pending, Accepted),
+        (Accepted, Ongoing),
+        (Accepted, Voting),
+        (Ongoing, Voting),
+        (Voting, VotingFinished),
+        (VotingFinished, Claim),
+        (VotingFinished, Disputed),
+        (Disputed, VotingFinished),
+        (Claim, Closed),
+        (VotingFinished, Ongoing),
+        (Pending, Cancelled),
+        (Accepted, Cancelled),
+        (Ongoing, Cancelled),
+        (Voting, Cancelled),
+        (VotingFinished, Cancelled),
+        (Disputed, Cancelled),
+    ];
+
+    #[test]
+    fn transition_table_allows_exactly_the_legal_moves() {
+        for from in ALL_STATES {
+            for to in ALL_STATES {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    LEGAL_TRANSITIONS.contains(&(from, to)),
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transition_table_rejects_backward_moves() {
+        for (from, to) in [
+            (Claim, Ongoing),
+            (Voting, Pending),
+            (Voting, Ongoing),
+            (Claim, VotingFinished),
+            (Closed, Claim),
+            (Cancelled, Pending),
+        ] {
+            assert!(!from.can_transition_to(to), "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn final_states_have_no_way_out() {
+        for to in ALL_STATES {
+            assert!(!Cancelled.can_transition_to(to));
+            assert!(!Closed.can_transition_to(to));
+        }
+    }
+
+    #[test]
+    fn no_state_transitions_to_itself() {
+        for state in ALL_STATES {
+            assert!(!state.can_transition_to(state), "{:?}", state);
+        }
+    }
+}
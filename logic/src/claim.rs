@@ -0,0 +1,215 @@
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState, PlacePayout, SettlementInfo};
+use crate::payout;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method for a single bettor to claim their share of a finished challenge.
+    // Only the caller's bets are looked at, so the cost does not grow with the
+    // number of bettors, and every winner pays the gas for their own payout.
+    pub fn claim(&mut self, challenge_id: u32) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let challenge = self.internal_get_challenge(challenge_id);
+
+        Self::assert_settleable(&challenge);
+
+        // The protocol fee comes off the pool before it is shared
+        let places = match &challenge.settlement {
+            Some(settlement) => settlement.places.clone(),
+            None => self.internal_places(
+                &challenge,
+                challenge.total_pool() - self.internal_protocol_fee(&challenge),
+            ),
+        };
+        let (stake, _) = self.internal_account_share(&challenge, &places, &account_id);
+        require!(stake > 0, "Nothing to claim for this account");
+        require!(
+            !self.has_claimed(challenge_id, account_id.clone()),
+            "Winnings have already been claimed"
+        );
+
+        self.internal_open_settlement(challenge_id, places);
+
+        let amount = self
+            .internal_settle_account(challenge_id, &account_id)
+            .unwrap_or(0);
+        U128(amount)
+    }
+
+    // Method to check whether an account has claimed its winnings for a challenge
+    pub fn has_claimed(&self, challenge_id: u32, account_id: AccountId) -> bool {
+        self.claimed_accounts
+            .get(&challenge_id)
+            .is_some_and(|claimed| claimed.contains(&account_id))
+    }
+
+    // Total an account has bet on the winning participants
+    pub(crate) fn internal_winning_stake(
+        &self,
+        challenge_id: u32,
+        winners: &[AccountId],
+        account_id: &AccountId,
+    ) -> u128 {
+        self.bets
+            .get(&challenge_id)
+            .and_then(|self_map| self_map.get(account_id))
+            .map_or(0, |account_bets| {
+                account_bets
+                    .iter()
+                    .filter(|(participant, _)| winners.contains(participant))
+                    .fold(0u128, |total, (_, bet)| total.saturating_add(bet))
+            })
+    }
+
+    // Mark one account as claimed and send its share of the settled places.
+    // Shares are rounded down, the last account to claim on the winning side receives
+    // the dust. Returns the amount sent, or None if there was nothing to settle.
+    pub(crate) fn internal_settle_account(
+        &mut self,
+        challenge_id: u32,
+        account_id: &AccountId,
+    ) -> Option<u128> {
+        let challenge = self.internal_get_challenge(challenge_id);
+        let settlement = challenge
+            .settlement
+            .as_ref()
+            .expect("Challenge has not been settled");
+
+        require!(
+            !Challenge::has_passed(challenge.claim_expires_at, env::block_timestamp()),
+            "The claim window of this challenge has expired"
+        );
+
+        let (stake, share) =
+            self.internal_account_share(&challenge, &settlement.places, account_id);
+        if stake == 0 || !self.internal_mark_claimed(challenge_id, account_id) {
+            return None;
+        }
+
+        let amount = payout::claim_share(
+            settlement.total_pool.0 - settlement.fee.0,
+            share,
+            stake,
+            challenge.total_staked_on(&settlement.winners()),
+            settlement.claimed_stake.0,
+            settlement.total_paid.0,
+        );
+
+        self.internal_record_payout(challenge, amount, stake);
+        if amount > 0 {
+            self.internal_record_received(account_id, challenge_id, amount, true);
+            self.transfer_token(account_id.clone(), amount, challenge_id);
+        }
+        Some(amount)
+    }
+
+    // Add the account to the claimed set, returns false if it was already there
+    pub(crate) fn internal_mark_claimed(
+        &mut self,
+        challenge_id: u32,
+        account_id: &AccountId,
+    ) -> bool {
+        let mut claim_prefix = challenge_id.to_be_bytes().to_vec();
+        claim_prefix.push(b'k'); // Add a byte to identify claims
+
+        let mut claimed = self
+            .claimed_accounts
+            .get(&challenge_id)
+            .unwrap_or_else(|| UnorderedSet::new(claim_prefix));
+
+        let inserted = claimed.insert(account_id);
+        self.claimed_accounts.insert(&challenge_id, &claimed);
+        inserted
+    }
+
+    // Settle the pool of a challenge on its first payout: the paid places and the protocol
+    // fee are fixed from then on, and the challenge moves to Claim.
+    pub(crate) fn internal_open_settlement(
+        &mut self,
+        challenge_id: u32,
+        places: Vec<PlacePayout>,
+    ) -> SettlementInfo {
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        if let Some(settlement) = challenge.settlement {
+            return settlement;
+        }
+
+        require!(
+            !places.is_empty(),
+            "Nobody backed a ranked participant, cancel the challenge to refund the stakes"
+        );
+        let fee = self.internal_protocol_fee(&challenge);
+        let settlement = SettlementInfo {
+            places,
+            total_pool: U128(challenge.total_pool()),
+            fee: U128(fee),
+            total_paid: U128(0),
+            claimed_stake: U128(0),
+            settled_at: U64(env::block_timestamp()),
+        };
+        challenge.settlement = Some(settlement.clone());
+        self.challenges.insert(&challenge_id, &challenge);
+        self.internal_accrue_protocol_fee(challenge_id, fee);
+
+        if challenge.state == ChallengeState::VotingFinished {
+            self.update_set_challenge_state(challenge_id, ChallengeState::Claim);
+        }
+        settlement
+    }
+
+    // Keep the settlement info up to date after a payout
+    pub(crate) fn internal_record_payout(
+        &mut self,
+        mut challenge: Challenge,
+        amount: u128,
+        stake: u128,
+    ) {
+        let settlement = challenge
+            .settlement
+            .as_mut()
+            .expect("Challenge has not been settled");
+        settlement.total_paid = U128(settlement.total_paid.0.saturating_add(amount));
+        settlement.claimed_stake = U128(settlement.claimed_stake.0.saturating_add(stake));
+
+        self.challenges.insert(&challenge.id, &challenge);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn last_claimant_takes_the_rounding_dust() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, charlie(), 1, alice(), 100);
+        bet(&mut contract, dave(), 1, alice(), 101);
+        vote(&mut contract, alice(), 1, vec![alice()]);
+        vote(&mut contract, bob(), 1, vec![alice()]);
+
+        // 401 staked, 10 in fees, 391 shared by 301 on alice
+        let mut paid = Vec::new();
+        for claimant in [alice(), charlie(), dave()] {
+            context(claimant, 1);
+            paid.push(contract.claim(1).0);
+        }
+        assert_eq!(paid, vec![129, 129, 133]);
+
+        let settlement = contract
+            .get_challenge_details(1)
+            .unwrap()
+            .settlement
+            .unwrap();
+        assert_eq!(settlement.total_paid, U128(391));
+        assert_eq!(paid.iter().sum::<u128>(), 391);
+        assert_eq!(settlement.total_pool.0 - settlement.fee.0, 391);
+    }
+}
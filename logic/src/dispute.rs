@@ -0,0 +1,277 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState, Dispute, VotingOutcome};
+use crate::events::ArenaEvent;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// A dispute the arbiter has not ruled on by then can be expired by anyone, which cancels
+// the challenge and returns the bond
+pub const DISPUTE_RESOLUTION_TIMEOUT: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Public Method - but only callable by env::current_account_id()
+    // adds a protocol-wide arbiter
+    #[private]
+    pub fn add_arbiter(&mut self, account_id: AccountId) {
+        self.arbiters.insert(&account_id);
+    }
+
+    // Public Method - but only callable by env::current_account_id()
+    // removes a protocol-wide arbiter
+    #[private]
+    pub fn remove_arbiter(&mut self, account_id: AccountId) {
+        self.arbiters.remove(&account_id);
+    }
+
+    // Method to get the protocol-wide arbiters
+    pub fn get_arbiters(&self) -> Vec<AccountId> {
+        self.arbiters.to_vec()
+    }
+
+    // Method for the arbiter of a disputed challenge to submit the binding result,
+    // a ranking of the participants, best first. The disputer gets the bond back if
+    // the winner changes, and forfeits it to the protocol if the result stands.
+    pub fn resolve_dispute(&mut self, challenge_id: u32, ranking: Vec<AccountId>) {
+        let account_id = env::predecessor_account_id();
+        let challenge = self.internal_get_challenge(challenge_id);
+
+        Self::assert_challenge_state(
+            &challenge,
+            &[ChallengeState::Disputed],
+            "Only a disputed challenge can be resolved",
+        );
+        require!(
+            self.internal_is_arbiter(&challenge, &account_id),
+            "Only the arbiter of this challenge can resolve its dispute"
+        );
+
+        let previous_winner = match &challenge.outcome {
+            Some(VotingOutcome::Decided(winner)) => Some(winner.clone()),
+            _ => None,
+        };
+        self.internal_set_result(challenge_id, &ranking);
+
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        let dispute = challenge.dispute.as_mut().expect("Dispute not found");
+        dispute.resolved_at = Some(U64(env::block_timestamp()));
+        let (disputer, bond) = (dispute.disputer.clone(), dispute.bond.0);
+        // The ruling is final, claims open right away
+        challenge.dispute_deadline = None;
+        challenge.keep_claims_open(env::block_timestamp());
+        self.challenges.insert(&challenge_id, &challenge);
+        self.update_set_challenge_state(challenge_id, ChallengeState::VotingFinished);
+
+        let upheld = previous_winner.as_ref() != Some(&ranking[0]);
+        ArenaEvent::DisputeResolved {
+            challenge_id,
+            arbiter: account_id,
+            ranking,
+            upheld,
+        }
+        .emit();
+        if !upheld {
+            ArenaEvent::BondForfeited {
+                challenge_id,
+                disputer,
+                amount: U128(bond),
+            }
+            .emit();
+            self.internal_accrue_protocol_fee(challenge_id, bond);
+        } else {
+            ArenaEvent::BondReturned {
+                challenge_id,
+                disputer: disputer.clone(),
+                amount: U128(bond),
+            }
+            .emit();
+            self.internal_refund(Some(challenge_id), challenge.token.clone(), disputer, bond);
+        }
+    }
+
+    // Open a dispute with the bond deposited through ft_transfer_call.
+    // Returns the part of the deposit above the bond, which goes back to the sender.
+    pub(crate) fn internal_open_dispute(
+        &mut self,
+        account_id: AccountId,
+        challenge_id: u32,
+        amount: u128,
+    ) -> u128 {
+        let mut challenge = self.internal_get_challenge(challenge_id);
+
+        Self::assert_challenge_state(
+            &challenge,
+            &[ChallengeState::VotingFinished],
+            "Only a finished vote can be disputed",
+        );
+        let terms = challenge
+            .dispute_terms
+            .clone()
+            .expect("This challenge cannot be disputed");
+        require!(
+            challenge.dispute.is_none(),
+            "This challenge has already been disputed"
+        );
+        require!(
+            !Challenge::has_passed(challenge.dispute_deadline, env::block_timestamp()),
+            "The dispute window of this challenge has closed"
+        );
+        require!(
+            challenge.side_of(&account_id).is_some(),
+            "Only participants can dispute a challenge"
+        );
+        require!(
+            amount >= terms.bond.0,
+            format!("Disputing this challenge needs a bond of {}", terms.bond.0)
+        );
+
+        // The bond is held until the arbiter rules
+        self.internal_add_owed(&challenge.token, terms.bond.0);
+        challenge.dispute = Some(Dispute {
+            disputer: account_id.clone(),
+            bond: terms.bond,
+            opened_at: U64(env::block_timestamp()),
+            resolved_at: None,
+        });
+        self.challenges.insert(&challenge_id, &challenge);
+        self.update_set_challenge_state(challenge_id, ChallengeState::Disputed);
+
+        ArenaEvent::DisputeOpened {
+            challenge_id,
+            disputer: account_id,
+            bond: terms.bond,
+        }
+        .emit();
+        amount - terms.bond.0
+    }
+
+    // Whether the dispute of a challenge has been open for DISPUTE_RESOLUTION_TIMEOUT
+    pub(crate) fn is_dispute_expired(challenge: &Challenge, now: u64) -> bool {
+        challenge.state == ChallengeState::Disputed
+            && challenge.dispute.as_ref().is_some_and(|dispute| {
+                now >= dispute
+                    .opened_at
+                    .0
+                    .saturating_add(DISPUTE_RESOLUTION_TIMEOUT)
+            })
+    }
+
+    // Nobody ruled on the dispute in time: neither the vote nor the dispute can be trusted,
+    // so the challenge is cancelled and the disputer gets the bond back
+    pub(crate) fn internal_expire_dispute(&mut self, challenge_id: u32) {
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        let dispute = challenge.dispute.as_mut().expect("Dispute not found");
+        dispute.resolved_at = Some(U64(env::block_timestamp()));
+        let (disputer, bond) = (dispute.disputer.clone(), dispute.bond.0);
+        self.challenges.insert(&challenge_id, &challenge);
+        self.internal_cancel_challenge(challenge_id);
+
+        ArenaEvent::DisputeExpired { challenge_id }.emit();
+        ArenaEvent::BondReturned {
+            challenge_id,
+            disputer: disputer.clone(),
+            amount: U128(bond),
+        }
+        .emit();
+        self.internal_refund(Some(challenge_id), challenge.token, disputer, bond);
+    }
+
+    // Panics if a challenge could be disputed without anybody to resolve the dispute
+    pub(crate) fn assert_has_arbiter(&self, challenge: &Challenge) {
+        if let Some(terms) = &challenge.dispute_terms {
+            require!(
+                terms.arbiter.is_some() || !self.arbiters.is_empty(),
+                "A challenge that can be disputed needs its own arbiter while the protocol has none"
+            );
+        }
+    }
+
+    // The challenge's own arbiter if it has one, any protocol arbiter otherwise
+    fn internal_is_arbiter(&self, challenge: &Challenge, account_id: &AccountId) -> bool {
+        match challenge
+            .dispute_terms
+            .as_ref()
+            .and_then(|terms| terms.arbiter.as_ref())
+        {
+            Some(arbiter) => arbiter == account_id,
+            None => self.arbiters.contains(account_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+
+    use crate::challenge::{ChallengeState, VotingOutcome};
+    use crate::test_utils::*;
+
+    const DISPUTABLE: &str = r#","dispute_terms":{"period":"50","bond":"10","arbiter":null}"#;
+
+    // Alice won the vote with dave as the protocol arbiter, and bob disputes it
+    fn disputed() -> crate::ArenaProtocolContract {
+        let mut contract = contract();
+        contract.add_arbiter(dave());
+        live_challenge(&mut contract, &[alice(), bob()], 100, DISPUTABLE);
+        vote(&mut contract, alice(), 1, vec![alice(), bob()]);
+        vote(&mut contract, bob(), 1, vec![alice(), bob()]);
+
+        let msg = r#"{"action":"OpenDispute","challenge_id":1}"#.to_string();
+        transfer(&mut contract, bob(), 10, msg);
+        assert_eq!(contract.get_challenge(1), Some(ChallengeState::Disputed));
+        contract
+    }
+
+    #[test]
+    fn arbiters_are_added_and_removed() {
+        let mut contract = contract();
+        contract.add_arbiter(dave());
+        contract.add_arbiter(charlie());
+        contract.remove_arbiter(dave());
+        assert_eq!(contract.get_arbiters(), vec![charlie()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "A challenge that can be disputed needs its own arbiter")]
+    fn disputable_challenge_needs_an_arbiter() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, DISPUTABLE);
+    }
+
+    #[test]
+    fn overturned_result_returns_the_bond() {
+        let mut contract = disputed();
+        context(dave(), 10);
+        contract.resolve_dispute(1, vec![bob(), alice()]);
+
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.state, ChallengeState::VotingFinished);
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(bob())));
+        assert_eq!(challenge.dispute_deadline, None);
+        assert!(challenge.dispute.unwrap().resolved_at.is_some());
+        assert_eq!(contract.get_protocol_fees(None).accrued, U128(0));
+    }
+
+    #[test]
+    fn upheld_result_forfeits_the_bond() {
+        let mut contract = disputed();
+        context(dave(), 10);
+        contract.resolve_dispute(1, vec![alice(), bob()]);
+
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(alice())));
+        assert_eq!(contract.get_protocol_fees(None).accrued, U128(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the arbiter of this challenge can resolve its dispute")]
+    fn removed_arbiter_cannot_resolve() {
+        let mut contract = disputed();
+        context(arena(), 10);
+        contract.remove_arbiter(dave());
+        context(dave(), 10);
+        contract.resolve_dispute(1, vec![bob(), alice()]);
+    }
+}
@@ -0,0 +1,128 @@
+use near_sdk::json_types::U128;
+use near_sdk::{near, AccountId};
+
+use crate::challenge::{ChallengeFormat, ChallengeState};
+
+// NEP-297 events emitted as `EVENT_JSON:` logs, so indexers can rebuild the history of
+// every challenge without parsing free-form logs
+#[near(event_json(standard = "arina"))]
+pub enum ArenaEvent {
+    #[event_version("1.0.0")]
+    ChallengeCreated {
+        challenge_id: u32,
+        creator: AccountId,
+        token_id: AccountId,
+        link: String,
+        format: ChallengeFormat,
+        participants: Vec<AccountId>,
+    },
+
+    #[event_version("1.0.0")]
+    ChallengeAccepted {
+        challenge_id: u32,
+        account_id: AccountId,
+    },
+
+    // `amount` is the bet just placed, `total` what the account now has on the participant
+    #[event_version("1.0.0")]
+    BetPlaced {
+        challenge_id: u32,
+        account_id: AccountId,
+        participant: AccountId,
+        amount: U128,
+        total: U128,
+    },
+
+    #[event_version("1.0.0")]
+    VoteCast {
+        challenge_id: u32,
+        voter: AccountId,
+        ranking: Vec<AccountId>,
+        weight: U128,
+    },
+
+    #[event_version("1.0.0")]
+    RematchRequested {
+        challenge_id: u32,
+        account_id: AccountId,
+    },
+
+    #[event_version("1.0.0")]
+    StateChanged {
+        challenge_id: u32,
+        from: ChallengeState,
+        to: ChallengeState,
+    },
+
+    #[event_version("1.0.0")]
+    DisputeOpened {
+        challenge_id: u32,
+        disputer: AccountId,
+        bond: U128,
+    },
+
+    // `upheld` tells whether the ruling changed the winner
+    #[event_version("1.0.0")]
+    DisputeResolved {
+        challenge_id: u32,
+        arbiter: AccountId,
+        ranking: Vec<AccountId>,
+        upheld: bool,
+    },
+
+    // Nobody ruled within DISPUTE_RESOLUTION_TIMEOUT, the challenge is cancelled
+    #[event_version("1.0.0")]
+    DisputeExpired { challenge_id: u32 },
+
+    // The bond goes to the protocol fees
+    #[event_version("1.0.0")]
+    BondForfeited {
+        challenge_id: u32,
+        disputer: AccountId,
+        amount: U128,
+    },
+
+    // The bond is refunded, the transfer itself is reported by a `refund` event
+    #[event_version("1.0.0")]
+    BondReturned {
+        challenge_id: u32,
+        disputer: AccountId,
+        amount: U128,
+    },
+
+    #[event_version("1.0.0")]
+    PayoutSent {
+        challenge_id: u32,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: U128,
+    },
+
+    // `retryable` tells whether the winnings can be claimed again, otherwise the payout
+    // is kept as a pending refund
+    #[event_version("1.0.0")]
+    PayoutFailed {
+        challenge_id: u32,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: U128,
+        retryable: bool,
+    },
+
+    // Refunds retried with `retry_refund` are not tied to a challenge
+    #[event_version("1.0.0")]
+    Refund {
+        challenge_id: Option<u32>,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: U128,
+        success: bool,
+    },
+
+    #[event_version("1.0.0")]
+    FeeAccrued {
+        challenge_id: u32,
+        token_id: AccountId,
+        amount: U128,
+    },
+}
@@ -0,0 +1,134 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, near_bindgen, require, AccountId, NearToken, Promise, PromiseResult};
+
+use crate::arena::{ext_ft_contract, MIN_GAS_FOR_FT_TRANSFER};
+use crate::challenge::Challenge;
+use crate::events::ArenaEvent;
+use crate::payout;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// Protocol fee configuration and totals
+#[near(serializers = [json])]
+pub struct ProtocolFees {
+    pub token_id: AccountId,
+    pub fee_basis_points: u32,
+    pub accrued: U128,
+    pub withdrawn: U128,
+    pub available: U128,
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Public Method - but only callable by env::current_account_id()
+    // sets the fee taken out of each settled pool, in basis points
+    #[private]
+    pub fn set_protocol_fee(&mut self, protocol_fee_bps: u32) {
+        Self::assert_valid_protocol_fee(protocol_fee_bps);
+        self.protocol_fee_bps = protocol_fee_bps;
+    }
+
+    // Public Method - get the fee configuration and the fees accrued and withdrawn in a
+    // token, the default token if none is given
+    pub fn get_protocol_fees(&self, token_id: Option<AccountId>) -> ProtocolFees {
+        let token_id = self.internal_token_or_default(token_id);
+        let ledger = self.internal_ledger(&token_id);
+        ProtocolFees {
+            token_id,
+            fee_basis_points: self.protocol_fee_bps,
+            accrued: U128(ledger.accrued_protocol_fees),
+            withdrawn: U128(ledger.withdrawn_protocol_fees),
+            available: U128(ledger.accrued_protocol_fees - ledger.withdrawn_protocol_fees),
+        }
+    }
+
+    // Method for the protocol account to withdraw fees accrued in a token, all of them
+    // and in the default token by default
+    pub fn withdraw_protocol_fees(
+        &mut self,
+        amount: Option<U128>,
+        token_id: Option<AccountId>,
+    ) -> Promise {
+        require!(
+            env::predecessor_account_id() == self.protocol_account,
+            "Only the protocol account can withdraw fees"
+        );
+
+        let token_id = self.internal_token_or_default(token_id);
+        let ledger = self.internal_ledger(&token_id);
+        let available = ledger.accrued_protocol_fees - ledger.withdrawn_protocol_fees;
+        let amount = amount.map_or(available, |amount| amount.0);
+        require!(amount > 0, "No protocol fees to withdraw");
+        require!(
+            amount <= available,
+            format!(
+                "Cannot withdraw {}, only {} is available",
+                amount, available
+            )
+        );
+
+        // Counted as withdrawn right away so it cannot be withdrawn twice, but owed until
+        // the callback sees the transfer succeed
+        self.internal_update_ledger(&token_id, |ledger| ledger.withdrawn_protocol_fees += amount);
+
+        ext_ft_contract::ext(token_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(self.protocol_account.clone(), U128(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(MIN_GAS_FOR_FT_TRANSFER)
+                    .on_protocol_fee_transfer(U128(amount), token_id),
+            )
+    }
+
+    #[private]
+    pub fn on_protocol_fee_transfer(&mut self, amount: U128, token_id: AccountId) -> bool {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                self.internal_sub_owed(&token_id, amount.0);
+                true
+            }
+            PromiseResult::Failed => {
+                // Nothing left the contract, the fees can be withdrawn again
+                self.internal_update_ledger(&token_id, |ledger| {
+                    ledger.withdrawn_protocol_fees -= amount.0
+                });
+
+                env::log_str(&format!(
+                    "Protocol fee withdrawal of {} {} failed, fees restored",
+                    amount.0, token_id
+                ));
+                false
+            }
+        }
+    }
+
+    // The fee for a challenge, fixed once the challenge has been settled
+    pub(crate) fn internal_protocol_fee(&self, challenge: &Challenge) -> u128 {
+        match &challenge.settlement {
+            Some(settlement) => settlement.fee.0,
+            None => payout::apply_basis_points(challenge.total_pool(), self.protocol_fee_bps),
+        }
+    }
+
+    pub(crate) fn internal_accrue_protocol_fee(&mut self, challenge_id: u32, fee: u128) {
+        if fee == 0 {
+            return;
+        }
+        let token_id = self.internal_get_challenge(challenge_id).token;
+        self.internal_update_ledger(&token_id, |ledger| ledger.accrued_protocol_fees += fee);
+        ArenaEvent::FeeAccrued {
+            challenge_id,
+            token_id,
+            amount: U128(fee),
+        }
+        .emit();
+    }
+
+    pub(crate) fn assert_valid_protocol_fee(protocol_fee_bps: u32) {
+        require!(
+            protocol_fee_bps <= payout::BASIS_POINTS,
+            "Protocol fee cannot exceed 100%"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState};
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// Number of challenges returned by a listing view when no limit is given
+pub const DEFAULT_PAGE_LIMIT: u64 = 50;
+
+// Storage prefix of a per-account collection. The account is hashed so one account's
+// prefix is never the start of another's.
+pub(crate) fn account_prefix(tag: u8, account_id: &AccountId) -> Vec<u8> {
+    let mut prefix = vec![tag];
+    prefix.extend(env::sha256(account_id.as_bytes()));
+    prefix
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method to list challenges in creation order, or only those in `state_filter`.
+    // Challenges move between states, so the order of a filtered listing can change.
+    pub fn get_challenges(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        state_filter: Option<ChallengeState>,
+    ) -> Vec<Challenge> {
+        match state_filter {
+            Some(state) => self.internal_challenges_page(
+                self.challenges_by_state.get(&state),
+                from_index,
+                limit,
+            ),
+            None => self
+                .challenges
+                .values_as_vector()
+                .iter()
+                .skip(from_index.unwrap_or(0) as usize)
+                .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+                .collect(),
+        }
+    }
+
+    // Method to list the challenges created by an account, oldest first
+    pub fn get_challenges_by_creator(
+        &self,
+        creator: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Challenge> {
+        self.internal_challenges_page(self.challenges_by_creator.get(&creator), from_index, limit)
+    }
+
+    // Method to list the challenges an account plays in, as a participant or a team member
+    pub fn get_challenges_by_participant(
+        &self,
+        participant: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Challenge> {
+        self.internal_challenges_page(
+            self.challenges_by_participant.get(&participant),
+            from_index,
+            limit,
+        )
+    }
+
+    // Add a new challenge to the creator, participant and state indexes
+    pub(crate) fn internal_index_challenge(&mut self, challenge: &Challenge) {
+        Self::insert_index_entry(
+            &mut self.challenges_by_creator,
+            b'o',
+            &challenge.creator,
+            challenge.id,
+        );
+
+        let mut players: Vec<&AccountId> = Vec::new();
+        for participant in challenge.participants.iter() {
+            players.push(&participant.account_id);
+            players.extend(participant.members.iter());
+        }
+        for player in players {
+            Self::insert_index_entry(
+                &mut self.challenges_by_participant,
+                b'p',
+                player,
+                challenge.id,
+            );
+        }
+
+        self.internal_index_state(challenge.id, None, challenge.state);
+    }
+
+    // Move a challenge from the set of its previous state to the set of its new state
+    pub(crate) fn internal_index_state(
+        &mut self,
+        challenge_id: u32,
+        prev_state: Option<ChallengeState>,
+        new_state: ChallengeState,
+    ) {
+        if let Some(prev_state) = prev_state {
+            if let Some(mut ids) = self.challenges_by_state.get(&prev_state) {
+                ids.remove(&challenge_id);
+                self.challenges_by_state.insert(&prev_state, &ids);
+            }
+        }
+
+        let mut ids = self.challenges_by_state.get(&new_state).unwrap_or_else(|| {
+            let mut state_prefix = b"s".to_vec();
+            state_prefix.push(new_state as u8); // Add a byte to identify the state
+            UnorderedSet::new(state_prefix)
+        });
+        ids.insert(&challenge_id);
+        self.challenges_by_state.insert(&new_state, &ids);
+    }
+
+    fn insert_index_entry(
+        index: &mut UnorderedMap<AccountId, UnorderedSet<u32>>,
+        tag: u8,
+        account_id: &AccountId,
+        challenge_id: u32,
+    ) {
+        let mut ids = index
+            .get(account_id)
+            .unwrap_or_else(|| UnorderedSet::new(account_prefix(tag, account_id)));
+        ids.insert(&challenge_id);
+        index.insert(account_id, &ids);
+    }
+
+    fn internal_challenges_page(
+        &self,
+        ids: Option<UnorderedSet<u32>>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Challenge> {
+        let Some(ids) = ids else {
+            return Vec::new();
+        };
+        ids.as_vector()
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .filter_map(|challenge_id| self.challenges.get(&challenge_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::challenge::{Challenge, ChallengeState};
+    use crate::test_utils::*;
+
+    fn ids(challenges: Vec<Challenge>) -> Vec<u32> {
+        challenges.iter().map(|challenge| challenge.id).collect()
+    }
+
+    #[test]
+    fn listing_pages_through_challenges_in_creation_order() {
+        let mut contract = contract();
+        for _ in 0..3 {
+            create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        }
+        assert_eq!(
+            ids(contract.get_challenges(None, None, None)),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            ids(contract.get_challenges(Some(1), Some(1), None)),
+            vec![2]
+        );
+        assert!(contract.get_challenges(Some(3), None, None).is_empty());
+    }
+
+    #[test]
+    fn state_listing_follows_the_challenges() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        accept(&mut contract, bob(), 1, 100);
+
+        let pending = contract.get_challenges(None, None, Some(ChallengeState::Pending));
+        assert_eq!(ids(pending), vec![2]);
+        let accepted = contract.get_challenges(None, None, Some(ChallengeState::Accepted));
+        assert_eq!(ids(accepted), vec![1]);
+        assert!(contract
+            .get_challenges(None, None, Some(ChallengeState::Closed))
+            .is_empty());
+    }
+
+    #[test]
+    fn accounts_list_the_challenges_they_created_and_play_in() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        create_challenge(&mut contract, &[charlie(), alice()], 100, "");
+
+        assert_eq!(
+            ids(contract.get_challenges_by_creator(alice(), None, None)),
+            vec![1]
+        );
+        assert_eq!(
+            ids(contract.get_challenges_by_participant(alice(), None, None)),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(contract.get_challenges_by_participant(alice(), Some(1), Some(5))),
+            vec![2]
+        );
+        assert_eq!(
+            ids(contract.get_challenges_by_participant(bob(), None, None)),
+            vec![1]
+        );
+        assert!(contract
+            .get_challenges_by_creator(dave(), None, None)
+            .is_empty());
+    }
+}
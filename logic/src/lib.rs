@@ -1,30 +1,76 @@
 // Find all our documentation at https://docs.near.org
-use near_sdk::collections::{LazyOption, LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
-use near_sdk::serde::Serialize;
-use near_sdk::{env, log, near, near_bindgen, require, AccountId, NearSchema, NearToken, Promise};
+use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::{env, near, AccountId};
 
+use crate::challenge::{Challenge, ChallengeState};
+use crate::tokens::TokenLedger;
+
+mod admin;
 mod arena;
+mod challenge;
+mod claim;
+mod dispute;
+mod events;
+mod fees;
+mod index;
+mod migrate;
+mod odds;
+mod oracle;
+mod payout;
+mod phases;
+mod portfolio;
+mod refund;
+mod settlement;
+#[cfg(test)]
+mod test_utils;
+mod tokens;
+mod voting;
 
 // Define the contract structure
 #[near(contract_state)]
 pub struct ArenaProtocolContract {
     pub protocol_account: AccountId,
+    // Token of the challenges that do not name one, and of the views that are not given one
     pub ft_contract: AccountId,
 
+    // Fee taken out of each settled pool, in basis points
+    pub protocol_fee_bps: u32,
+
     pub challenge_counter: u32,
 
-    // Winner: UnorderedMap of AccountId -> (UnorderedMap of ChallengeID -> UnorderedMap of account_id -> new_vote)
-    pub winners: UnorderedMap<u32, UnorderedMap<AccountId, u8>>,
+    // Winner: UnorderedMap of ChallengeID -> UnorderedMap of participant -> vote weight
+    pub winners: UnorderedMap<u32, UnorderedMap<AccountId, u128>>,
 
     // Bet: UnorderedMap of ChallengeID -> (UnorderedMap of self_account -> UnorderedMap of account_id -> amount)
     pub bets: UnorderedMap<u32, UnorderedMap<AccountId, UnorderedMap<AccountId, u128>>>,
 
-    // Challenge: UnorderedMap of unique ID -> Link
-    pub challenges: UnorderedMap<u32, String>,
+    // Challenge: UnorderedMap of unique ID -> Challenge record
+    pub challenges: UnorderedMap<u32, Challenge>,
 
-    // Store which accounts have voted for each challenge
+    // Store which voters have voted for each challenge: participants, or bettors for stake-weighted votes
     pub voted_accounts: UnorderedMap<u32, UnorderedSet<AccountId>>, // New field
 
+    // Refunds whose ft_transfer failed: UnorderedMap of (account, token) -> amount owed until retried
+    pub pending_refunds: UnorderedMap<(AccountId, AccountId), u128>,
+
+    // Store which accounts have claimed their winnings for each challenge
+    pub claimed_accounts: UnorderedMap<u32, UnorderedSet<AccountId>>,
+
+    // Protocol-wide arbiters, resolving disputes of challenges without their own arbiter
+    pub arbiters: UnorderedSet<AccountId>,
+
+    // Secondary indexes of challenge IDs, so challenges can be listed without a full scan
+    pub challenges_by_state: UnorderedMap<ChallengeState, UnorderedSet<u32>>,
+    pub challenges_by_creator: UnorderedMap<AccountId, UnorderedSet<u32>>,
+    pub challenges_by_participant: UnorderedMap<AccountId, UnorderedSet<u32>>,
+
+    // Portfolio: UnorderedMap of bettor -> (UnorderedMap of ChallengeID -> amount paid back)
+    pub account_positions: UnorderedMap<AccountId, UnorderedMap<u32, u128>>,
+
+    // NEP-141 tokens new challenges can be denominated in
+    pub supported_tokens: UnorderedSet<AccountId>,
+    // What is owed to bettors and to the protocol, per token
+    pub token_ledgers: UnorderedMap<AccountId, TokenLedger>,
 }
 
 // Define the default, which automatically initializes the contract
@@ -33,11 +79,21 @@ impl Default for ArenaProtocolContract {
         Self {
             protocol_account: "v2.faucet.nonofficial.testnet".parse().unwrap(),
             ft_contract: "v2.faucet.nonofficial.testnet".parse().unwrap(),
+            protocol_fee_bps: 0,
             challenge_counter: 1,
             winners: UnorderedMap::new(b"w"),
             bets: UnorderedMap::new(b"b"),
             challenges: UnorderedMap::new(b"c"),
             voted_accounts: UnorderedMap::new(b"v"), // Initializing new field
+            pending_refunds: UnorderedMap::new(b"r"),
+            claimed_accounts: UnorderedMap::new(b"k"),
+            arbiters: UnorderedSet::new(b"a"),
+            challenges_by_state: UnorderedMap::new(b"s"),
+            challenges_by_creator: UnorderedMap::new(b"o"),
+            challenges_by_participant: UnorderedMap::new(b"p"),
+            account_positions: UnorderedMap::new(b"h"),
+            supported_tokens: UnorderedSet::new(b"t"),
+            token_ledgers: UnorderedMap::new(b"l"),
         }
     }
 }
@@ -46,20 +102,38 @@ impl Default for ArenaProtocolContract {
 #[near]
 impl ArenaProtocolContract {
     // Public Method - but only callable by env::current_account_id()
-    // initializes the contract with a beneficiary
+    // initializes the contract with a beneficiary and its fee in basis points
     #[init]
     #[private]
-    pub fn init(new_protocol_account: AccountId, new_ft_contract: AccountId) -> Self {
+    pub fn init(
+        new_protocol_account: AccountId,
+        new_ft_contract: AccountId,
+        protocol_fee_bps: u32,
+    ) -> Self {
         assert!(!env::state_exists(), "Already initialized");
-        Self {
+        Self::assert_valid_protocol_fee(protocol_fee_bps);
+        let mut contract = Self {
             protocol_account: new_protocol_account,
             challenge_counter: 1,
             ft_contract: new_ft_contract,
+            protocol_fee_bps,
             winners: UnorderedMap::new(b"w"),
             bets: UnorderedMap::new(b"b"),
             challenges: UnorderedMap::new(b"c"),
             voted_accounts: UnorderedMap::new(b"v"), // Initializing new field
-        }
+            pending_refunds: UnorderedMap::new(b"r"),
+            claimed_accounts: UnorderedMap::new(b"k"),
+            arbiters: UnorderedSet::new(b"a"),
+            challenges_by_state: UnorderedMap::new(b"s"),
+            challenges_by_creator: UnorderedMap::new(b"o"),
+            challenges_by_participant: UnorderedMap::new(b"p"),
+            account_positions: UnorderedMap::new(b"h"),
+            supported_tokens: UnorderedSet::new(b"t"),
+            token_ledgers: UnorderedMap::new(b"l"),
+        };
+        // The default token is supported from the start
+        contract.supported_tokens.insert(&contract.ft_contract);
+        contract
     }
 
     // Public Method - get the current beneficiary
@@ -0,0 +1,404 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near, AccountId};
+
+use crate::challenge::{
+    Challenge, ChallengeFormat, ChallengeState, ConsensusRule, PlacePayout, SettlementInfo,
+    VotingOutcome, VotingPolicy,
+};
+use crate::payout;
+use crate::tokens::TokenLedger;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// Contract state as deployed before challenges were stored as `Challenge` records
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(BorshSerialize))]
+#[borsh(crate = "near_sdk::borsh")]
+struct LegacyArenaProtocolContract {
+    protocol_account: AccountId,
+    ft_contract: AccountId,
+    challenge_counter: u32,
+    winners: UnorderedMap<u32, UnorderedMap<AccountId, u8>>,
+    bets: UnorderedMap<u32, UnorderedMap<AccountId, UnorderedMap<AccountId, u128>>>,
+    challenges: UnorderedMap<u32, String>,
+    voted_accounts: UnorderedMap<u32, UnorderedSet<AccountId>>,
+}
+
+#[near]
+impl ArenaProtocolContract {
+    // Public Method - but only callable by env::current_account_id()
+    // converts the legacy state strings in `challenges` into `Challenge` records
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let mut old: LegacyArenaProtocolContract =
+            env::state_read().expect("Contract state not found");
+        let now = env::block_timestamp();
+
+        // Stakes of challenges that were not paid out yet are still owed to the bettors
+        let mut total_owed: u128 = 0;
+        // Challenges the first version already paid out
+        let mut paid_out: Vec<u32> = Vec::new();
+
+        // The records are rewritten in place, under the same `b"c"` prefix
+        for (challenge_id, state) in old.challenges.to_vec() {
+            // The link was never stored, and the creator was the first account to bet
+            let bets = old.bets.get(&challenge_id);
+            let creator = bets
+                .as_ref()
+                .and_then(|self_map| self_map.keys().next())
+                .unwrap_or_else(|| old.protocol_account.clone());
+
+            let mut challenge = Challenge {
+                id: challenge_id,
+                creator,
+                link: String::new(),
+                format: ChallengeFormat::MultiPlayer,
+                token: old.ft_contract.clone(),
+                participants: Vec::new(),
+                // There was no acceptance step, so nothing is left to accept
+                acceptance_stake: U128(0),
+                acceptance_deadline: U64(now),
+                // Every bettor used to vote once, so the votes cast so far and the ones
+                // still to come are all counted the same
+                voting_policy: VotingPolicy::OneVotePerBettor,
+                // Results had no consensus check before, a majority is the closest rule
+                consensus_rule: ConsensusRule::Majority,
+                payout_schedule: vec![payout::BASIS_POINTS],
+                betting_closes_at: None,
+                voting_closes_at: None,
+                claim_expires_at: None,
+                dispute_terms: None,
+                dispute_deadline: None,
+                dispute: None,
+                oracle: None,
+                // One vote per legacy voter
+                voted_weight: U128(
+                    old.voted_accounts
+                        .get(&challenge_id)
+                        .map_or(0, |voted_accounts| voted_accounts.len() as u128),
+                ),
+                outcome: None,
+                state: ChallengeState::from_legacy(&state).unwrap_or_else(|| {
+                    env::panic_str(&format!(
+                        "Unknown legacy state {:?} for challenge {}",
+                        state, challenge_id
+                    ))
+                }),
+                created_at: U64(now),
+                updated_at: U64(now),
+                settlement: None,
+            };
+
+            // Everyone that was bet on becomes an accepted participant
+            if let Some(self_map) = bets {
+                for (_, amount_map) in self_map.iter() {
+                    for (participant, amount) in amount_map.iter() {
+                        if !challenge.is_participant(&participant) {
+                            challenge.add_participant(
+                                participant.clone(),
+                                vec![participant.clone()],
+                                true,
+                            );
+                        }
+                        challenge.add_stake(&participant, amount);
+                    }
+                }
+            }
+
+            // Every vote listed the participants it picked as winner, so the counts
+            // also serve as first places
+            if let Some(participant_map) = old.winners.get(&challenge_id) {
+                for (participant, votes) in participant_map.iter() {
+                    challenge.add_first_place_votes(&participant, votes as u128);
+                }
+            }
+
+            // A legacy claim paid the whole pool to the backers of the most voted
+            // participants. Record it as settled so nobody can be paid a second time.
+            if challenge.state == ChallengeState::Claim {
+                let winners = legacy_winners(&challenge);
+                challenge.settlement = Some(legacy_settlement(&challenge, &winners, now));
+                challenge.outcome = winners.first().cloned().map(VotingOutcome::Decided);
+                paid_out.push(challenge_id);
+            }
+
+            if !matches!(
+                challenge.state,
+                ChallengeState::Claim | ChallengeState::Cancelled
+            ) {
+                total_owed = total_owed.saturating_add(challenge.total_pool());
+            }
+
+            let value = borsh::to_vec(&challenge).expect("Failed to serialize challenge");
+            let key = borsh::to_vec(&challenge_id).expect("Failed to serialize challenge id");
+            old.challenges.insert_raw(&key, &value);
+        }
+
+        // Vote counts were stored as u8, they are Borda points now
+        for (_, mut participant_map) in old.winners.iter() {
+            for (participant, votes) in participant_map.to_vec() {
+                let key = borsh::to_vec(&participant).expect("Failed to serialize participant");
+                let value = borsh::to_vec(&(votes as u128)).expect("Failed to serialize votes");
+                participant_map.insert_raw(&key, &value);
+            }
+        }
+
+        env::log_str(&format!(
+            "Migrated {} challenges to typed records",
+            old.challenges.len()
+        ));
+
+        let mut contract = Self {
+            protocol_account: old.protocol_account,
+            ft_contract: old.ft_contract,
+            protocol_fee_bps: 0,
+            challenge_counter: old.challenge_counter,
+            winners: retype(&old.winners),
+            bets: old.bets,
+            challenges: retype(&old.challenges),
+            voted_accounts: old.voted_accounts,
+            pending_refunds: UnorderedMap::new(b"r"),
+            claimed_accounts: UnorderedMap::new(b"k"),
+            arbiters: UnorderedSet::new(b"a"),
+            challenges_by_state: UnorderedMap::new(b"s"),
+            challenges_by_creator: UnorderedMap::new(b"o"),
+            challenges_by_participant: UnorderedMap::new(b"p"),
+            account_positions: UnorderedMap::new(b"h"),
+            supported_tokens: UnorderedSet::new(b"t"),
+            token_ledgers: UnorderedMap::new(b"l"),
+        };
+
+        // Every legacy challenge was played for the single token of the first version
+        contract.supported_tokens.insert(&contract.ft_contract);
+        contract.token_ledgers.insert(
+            &contract.ft_contract,
+            &TokenLedger {
+                total_owed,
+                ..Default::default()
+            },
+        );
+
+        // The listing indexes did not exist yet
+        for challenge in contract.challenges.values_as_vector().to_vec() {
+            contract.internal_index_challenge(&challenge);
+        }
+        // Neither did portfolios
+        for (challenge_id, self_map) in contract.bets.to_vec() {
+            for account in self_map.keys() {
+                contract.internal_track_position(&account, challenge_id);
+            }
+        }
+        // Everyone with a stake on the winners of a paid out challenge has been paid
+        // their pro rata share
+        for challenge_id in paid_out {
+            let challenge = contract.internal_get_challenge(challenge_id);
+            let places = challenge
+                .settlement
+                .as_ref()
+                .map_or(Vec::new(), |settlement| settlement.places.clone());
+            let Some(self_map) = contract.bets.get(&challenge_id) else {
+                continue;
+            };
+            for account in self_map.keys() {
+                let (stake, share) = contract.internal_account_share(&challenge, &places, &account);
+                if stake > 0 {
+                    contract.internal_mark_claimed(challenge_id, &account);
+                    contract.internal_record_received(&account, challenge_id, share, true);
+                }
+            }
+        }
+        contract
+    }
+}
+
+// Participants with the most legacy votes, the ones the first version's `claim_winnings`
+// paid out. The migrated first place votes are exactly those vote counts.
+fn legacy_winners(challenge: &Challenge) -> Vec<AccountId> {
+    let max_votes = challenge
+        .participants
+        .iter()
+        .map(|entry| entry.first_place_votes.0)
+        .max()
+        .unwrap_or(0);
+    if max_votes == 0 {
+        return Vec::new();
+    }
+    challenge
+        .participants
+        .iter()
+        .filter(|entry| entry.first_place_votes.0 == max_votes)
+        .map(|entry| entry.account_id.clone())
+        .collect()
+}
+
+// Settlement of a pool the first version paid out in full, without a fee
+fn legacy_settlement(challenge: &Challenge, winners: &[AccountId], now: u64) -> SettlementInfo {
+    let total_pool = challenge.total_pool();
+    let winning_stake = challenge.total_staked_on(winners);
+    let places = winners
+        .iter()
+        .map(|participant| PlacePayout {
+            participant: participant.clone(),
+            position: 1,
+            amount: U128(payout::pro_rata_share(
+                total_pool,
+                challenge.total_staked_on(std::slice::from_ref(participant)),
+                winning_stake,
+            )),
+        })
+        .collect();
+
+    SettlementInfo {
+        places,
+        total_pool: U128(total_pool),
+        fee: U128(0),
+        total_paid: U128(total_pool),
+        claimed_stake: U128(winning_stake),
+        settled_at: U64(now),
+    }
+}
+
+// Reinterpret a collection with the element types its entries were rewritten to.
+// Only the prefix and the length live in the contract state, so they carry over as is.
+fn retype<A: BorshSerialize, B: BorshDeserialize>(collection: &A) -> B {
+    let bytes = borsh::to_vec(collection).expect("Failed to serialize collection");
+    borsh::from_slice(&bytes).expect("Failed to deserialize collection")
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::collections::{UnorderedMap, UnorderedSet};
+    use near_sdk::json_types::U128;
+    use near_sdk::{env, AccountId};
+
+    use super::LegacyArenaProtocolContract;
+    use crate::challenge::{ChallengeState, VotingOutcome, VotingPolicy};
+    use crate::test_utils::*;
+    use crate::ArenaProtocolContract;
+
+    // Stores a challenge the way the first version did: bets, vote counts and voters
+    fn legacy_challenge(
+        legacy: &mut LegacyArenaProtocolContract,
+        challenge_id: u32,
+        state: &str,
+        bets: &[(AccountId, AccountId, u128)],
+        votes: &[(AccountId, AccountId)],
+    ) {
+        let mut self_map = UnorderedMap::new(challenge_id.to_le_bytes().to_vec());
+        for (account, participant, amount) in bets {
+            let mut amount_prefix = challenge_id.to_le_bytes().to_vec();
+            amount_prefix.extend_from_slice(account.as_bytes());
+            let mut amount_map = UnorderedMap::new(amount_prefix);
+            amount_map.insert(participant, amount);
+            self_map.insert(account, &amount_map);
+        }
+        legacy.bets.insert(&challenge_id, &self_map);
+
+        let mut vote_prefix = challenge_id.to_be_bytes().to_vec();
+        vote_prefix.push(b's');
+        let mut voted_accounts = UnorderedSet::new(vote_prefix);
+        let mut winner_prefix = challenge_id.to_be_bytes().to_vec();
+        winner_prefix.push(b'z');
+        let mut participant_map = UnorderedMap::new(winner_prefix);
+        for (voter, participant) in votes {
+            voted_accounts.insert(voter);
+            let count: u8 = participant_map.get(participant).unwrap_or(0);
+            participant_map.insert(participant, &(count + 1));
+        }
+        legacy.voted_accounts.insert(&challenge_id, &voted_accounts);
+        legacy.winners.insert(&challenge_id, &participant_map);
+
+        legacy.challenges.insert(&challenge_id, &state.to_string());
+    }
+
+    // Challenge 1 was voted and paid out, challenge 2 is still being voted on
+    fn migrated() -> ArenaProtocolContract {
+        context(arena(), 0);
+        let mut legacy = LegacyArenaProtocolContract {
+            protocol_account: protocol(),
+            ft_contract: token(),
+            challenge_counter: 3,
+            winners: UnorderedMap::new(b"w"),
+            bets: UnorderedMap::new(b"b"),
+            challenges: UnorderedMap::new(b"c"),
+            voted_accounts: UnorderedMap::new(b"v"),
+        };
+        legacy_challenge(
+            &mut legacy,
+            1,
+            "4",
+            &[
+                (alice(), alice(), 100),
+                (bob(), bob(), 50),
+                (charlie(), alice(), 30),
+            ],
+            &[(alice(), alice()), (bob(), bob()), (charlie(), alice())],
+        );
+        legacy_challenge(
+            &mut legacy,
+            2,
+            "3",
+            &[
+                (alice(), alice(), 10),
+                (bob(), bob(), 100),
+                (charlie(), alice(), 10),
+            ],
+            &[(alice(), alice())],
+        );
+        env::state_write(&legacy);
+        ArenaProtocolContract::migrate()
+    }
+
+    #[test]
+    fn paid_out_challenge_is_settled() {
+        let contract = migrated();
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.state, ChallengeState::Claim);
+        assert_eq!(challenge.voting_policy, VotingPolicy::OneVotePerBettor);
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(alice())));
+
+        let settlement = challenge.settlement.unwrap();
+        assert_eq!(settlement.fee, U128(0));
+        assert_eq!(settlement.total_paid, U128(180));
+        assert_eq!(settlement.claimed_stake, U128(130));
+
+        // Both backers of the winner were paid, nothing is claimable again
+        assert!(contract.has_claimed(1, alice()));
+        assert!(contract.has_claimed(1, charlie()));
+        assert!(!contract.has_claimed(1, bob()));
+        let plan = contract.get_settlement_plan(1);
+        let paid: Vec<u128> = plan.payouts.iter().map(|payout| payout.amount.0).collect();
+        assert_eq!(paid, vec![138, 0, 41]);
+
+        // Only the stakes of the challenge still being voted on are owed
+        assert_eq!(contract.get_total_owed(None), U128(120));
+    }
+
+    #[test]
+    #[should_panic(expected = "Winnings have already been claimed")]
+    fn paid_out_challenge_cannot_be_claimed_again() {
+        let mut contract = migrated();
+        context(alice(), 1);
+        contract.claim(1);
+    }
+
+    #[test]
+    fn legacy_votes_count_one_per_bettor() {
+        let mut contract = migrated();
+        let challenge = contract.get_challenge_details(2).unwrap();
+        assert_eq!(
+            challenge.participant(&alice()).unwrap().first_place_votes,
+            U128(1)
+        );
+
+        // Two of three bettors pick alice, whatever bob staked
+        vote(&mut contract, bob(), 2, vec![bob()]);
+        vote(&mut contract, charlie(), 2, vec![alice()]);
+        let challenge = contract.get_challenge_details(2).unwrap();
+        assert_eq!(challenge.state, ChallengeState::VotingFinished);
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(alice())));
+    }
+}
@@ -0,0 +1,176 @@
+use near_sdk::json_types::U128;
+use near_sdk::{near, near_bindgen, require, AccountId};
+
+use crate::challenge::Challenge;
+use crate::payout;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// Current stake on a participant and what a winning bet on it pays back
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticipantOdds {
+    pub participant: AccountId,
+    pub total_staked: U128,
+    // Payout per token staked if the participant won now, in basis points (10000 = 1x).
+    // None while nobody has bet on the participant.
+    pub implied_multiplier_bps: Option<U128>,
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChallengeOdds {
+    pub challenge_id: u32,
+    pub total_pool: U128,
+    pub fee: U128,
+    pub participants: Vec<ParticipantOdds>,
+}
+
+// Projected result of a bet that has not been placed yet
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BetQuote {
+    pub challenge_id: u32,
+    pub participant: AccountId,
+    pub amount: U128,
+    // What the bet would pay back, after the protocol fee, if the participant won now
+    pub payout: U128,
+    pub implied_multiplier_bps: U128,
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method to get the parimutuel odds of every participant of a challenge. A win is
+    // worth the first place of the payout schedule, shared by everyone that backed the winner.
+    pub fn get_odds(&self, challenge_id: u32) -> ChallengeOdds {
+        let challenge = self.internal_get_challenge(challenge_id);
+        let total_pool = challenge.total_pool();
+        let fee = self.internal_protocol_fee(&challenge);
+        let first_place = self.internal_first_place_amount(&challenge, total_pool - fee);
+
+        // The participant totals are kept in step with the bets as they are placed
+        let participants = challenge
+            .participants
+            .iter()
+            .map(|entry| ParticipantOdds {
+                participant: entry.account_id.clone(),
+                total_staked: entry.total_staked,
+                implied_multiplier_bps: (entry.total_staked.0 > 0).then(|| {
+                    U128(payout::mul_div(
+                        first_place,
+                        payout::BASIS_POINTS as u128,
+                        entry.total_staked.0,
+                    ))
+                }),
+            })
+            .collect();
+
+        ChallengeOdds {
+            challenge_id,
+            total_pool: U128(total_pool),
+            fee: U128(fee),
+            participants,
+        }
+    }
+
+    // Method to quote a bet: what `amount` on `participant` would pay back after the
+    // protocol fee if the participant won right after the bet
+    pub fn quote_bet(&self, challenge_id: u32, participant: AccountId, amount: U128) -> BetQuote {
+        let challenge = self.internal_get_challenge(challenge_id);
+        require!(amount.0 > 0, "Amount must be positive");
+        require!(
+            challenge.is_participant(&participant),
+            "Bets can only be placed on a participant of the challenge"
+        );
+
+        // The bet grows the pool, and the fee with it
+        let total_pool = challenge.total_pool().saturating_add(amount.0);
+        let fee = payout::apply_basis_points(total_pool, self.protocol_fee_bps);
+        let first_place = self.internal_first_place_amount(&challenge, total_pool - fee);
+        let total_staked = challenge
+            .total_staked_on(std::slice::from_ref(&participant))
+            .saturating_add(amount.0);
+        let payout = payout::pro_rata_share(first_place, amount.0, total_staked);
+
+        BetQuote {
+            challenge_id,
+            participant,
+            amount,
+            payout: U128(payout),
+            implied_multiplier_bps: U128(payout::mul_div(
+                payout,
+                payout::BASIS_POINTS as u128,
+                amount.0,
+            )),
+        }
+    }
+
+    // Part of the distributable pool that goes to the first place of the schedule
+    fn internal_first_place_amount(&self, challenge: &Challenge, distributable: u128) -> u128 {
+        payout::apply_basis_points(distributable, challenge.payout_schedule[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+
+    use crate::test_utils::*;
+
+    fn multipliers(contract: &crate::ArenaProtocolContract) -> Vec<Option<u128>> {
+        contract
+            .get_odds(1)
+            .participants
+            .iter()
+            .map(|odds| odds.implied_multiplier_bps.map(|bps| bps.0))
+            .collect()
+    }
+
+    #[test]
+    fn odds_share_the_pool_among_the_backers() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, dave(), 1, alice(), 200);
+
+        // 400 staked, 10 in fees
+        let odds = contract.get_odds(1);
+        assert_eq!((odds.total_pool, odds.fee), (U128(400), U128(10)));
+        assert_eq!(multipliers(&contract), vec![Some(13_000), Some(39_000)]);
+    }
+
+    #[test]
+    fn odds_pay_the_first_place_of_the_schedule() {
+        let mut contract = contract();
+        create_challenge(
+            &mut contract,
+            &[alice(), bob(), charlie()],
+            100,
+            r#","payout_schedule":[7000,3000]"#,
+        );
+        // Nobody backed bob or charlie yet
+        assert_eq!(multipliers(&contract), vec![Some(6_800), None, None]);
+    }
+
+    #[test]
+    fn quote_matches_the_claim_of_the_bet() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        let quote = contract.quote_bet(1, bob(), U128(100));
+        assert_eq!(quote.payout, U128(146));
+        assert_eq!(quote.implied_multiplier_bps, U128(14_600));
+
+        bet(&mut contract, dave(), 1, bob(), 100);
+        vote(&mut contract, alice(), 1, vec![bob()]);
+        vote(&mut contract, bob(), 1, vec![bob()]);
+        context(dave(), 1);
+        assert_eq!(contract.claim(1), quote.payout);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bets can only be placed on a participant of the challenge")]
+    fn quote_only_backs_participants() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        contract.quote_bet(1, charlie(), U128(100));
+    }
+}
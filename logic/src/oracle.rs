@@ -0,0 +1,83 @@
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState};
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method for the oracle of a challenge to report its result, a ranking of the
+    // participants, best first. Voting is skipped and the result is settled like a
+    // decided vote.
+    pub fn resolve_with_oracle(&mut self, challenge_id: u32, standings: Vec<AccountId>) {
+        let challenge = self.internal_get_challenge(challenge_id);
+
+        require!(
+            challenge.oracle.as_ref() == Some(&env::predecessor_account_id()),
+            "Only the oracle of this challenge can resolve it"
+        );
+        Self::assert_challenge_state(
+            &challenge,
+            &[
+                ChallengeState::Accepted,
+                ChallengeState::Ongoing,
+                ChallengeState::Voting,
+            ],
+            "Cannot resolve a challenge in this state",
+        );
+        // Past the deadline the challenge is cancelled instead, see `advance_challenge`
+        require!(
+            !Challenge::has_passed(challenge.voting_closes_at, env::block_timestamp()),
+            "The oracle has to report before voting closes"
+        );
+
+        if challenge.state != ChallengeState::Voting {
+            self.update_set_challenge_state(challenge_id, ChallengeState::Voting);
+        }
+        self.internal_set_result(challenge_id, &standings);
+        self.internal_finish_voting(challenge_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::challenge::{ChallengeState, VotingOutcome};
+    use crate::test_utils::*;
+
+    // Alice and bob play, charlie reports the result between 100 and 200
+    fn oracle_challenge() -> crate::ArenaProtocolContract {
+        let mut contract = contract();
+        let extra = format!(
+            r#","betting_closes_at":"100","voting_closes_at":"200","oracle":"{}""#,
+            charlie()
+        );
+        live_challenge(&mut contract, &[alice(), bob()], 100, &extra);
+        contract
+    }
+
+    #[test]
+    fn oracle_report_decides_the_challenge() {
+        let mut contract = oracle_challenge();
+        context(charlie(), 150);
+        contract.resolve_with_oracle(1, vec![bob(), alice()]);
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.state, ChallengeState::VotingFinished);
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(bob())));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the oracle of this challenge can resolve it")]
+    fn only_the_oracle_can_report() {
+        let mut contract = oracle_challenge();
+        context(alice(), 150);
+        contract.resolve_with_oracle(1, vec![alice(), bob()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The oracle has to report before voting closes")]
+    fn late_oracle_report_is_rejected() {
+        let mut contract = oracle_challenge();
+        context(charlie(), 200);
+        contract.resolve_with_oracle(1, vec![bob(), alice()]);
+    }
+}
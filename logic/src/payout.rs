@@ -0,0 +1,226 @@
+use near_sdk::require;
+
+mod u256 {
+    // The macro expansion does not follow every clippy lint
+    #![allow(clippy::all)]
+
+    uint::construct_uint! {
+        // 256-bit unsigned integer, wide enough for the product of two u128 amounts
+        pub struct U256(4);
+    }
+}
+
+pub use u256::U256;
+
+// 100% expressed in basis points
+pub const BASIS_POINTS: u32 = 10_000;
+
+// floor(value * numerator / denominator), with a 256-bit intermediate product
+pub fn mul_div(value: u128, numerator: u128, denominator: u128) -> u128 {
+    require!(denominator > 0, "Division by zero in payout math");
+
+    let result = U256::from(value) * U256::from(numerator) / U256::from(denominator);
+    require!(
+        result <= U256::from(u128::MAX),
+        "Payout math overflows u128"
+    );
+    result.as_u128()
+}
+
+// The given share of an amount, rounded down
+pub fn apply_basis_points(amount: u128, basis_points: u32) -> u128 {
+    require!(
+        basis_points <= BASIS_POINTS,
+        "Basis points cannot exceed 100%"
+    );
+    mul_div(amount, basis_points as u128, BASIS_POINTS as u128)
+}
+
+// Panics unless the schedule pays every place something and adds up to 100%
+pub fn assert_valid_schedule(schedule: &[u32]) {
+    require!(
+        !schedule.is_empty(),
+        "A payout schedule needs at least one place"
+    );
+    require!(
+        schedule.iter().all(|basis_points| *basis_points > 0),
+        "Every place of a payout schedule has to be paid"
+    );
+    require!(
+        schedule
+            .iter()
+            .fold(0u64, |total, basis_points| total + *basis_points as u64)
+            == BASIS_POINTS as u64,
+        "A payout schedule has to add up to 100%"
+    );
+}
+
+// Payout for one stake, rounded down
+pub fn pro_rata_share(amount: u128, stake: u128, total_stake: u128) -> u128 {
+    if total_stake == 0 {
+        return 0;
+    }
+    mul_div(amount, stake, total_stake)
+}
+
+// Split `amount` in proportion to `weights`, every part rounded down
+pub fn split_by_weights(amount: u128, weights: &[u128]) -> Vec<u128> {
+    let total_weight = weights
+        .iter()
+        .fold(0u128, |total, weight| total.saturating_add(*weight));
+
+    weights
+        .iter()
+        .map(|weight| pro_rata_share(amount, *weight, total_weight))
+        .collect()
+}
+
+// Payout for a claim, where the last claimant on the winning side receives the dust
+// left over by everyone that claimed before them instead of their rounded down share.
+pub fn claim_share(
+    distributable: u128,
+    share: u128,
+    stake: u128,
+    total_winning_stake: u128,
+    claimed_stake: u128,
+    total_paid: u128,
+) -> u128 {
+    if stake > 0 && claimed_stake.saturating_add(stake) >= total_winning_stake {
+        distributable.saturating_sub(total_paid)
+    } else {
+        share
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUNDS: usize = 2_000;
+
+    // xorshift64*, seeded so a failing case can be replayed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        // Uniform enough in [low, high]
+        fn between(&mut self, low: u128, high: u128) -> u128 {
+            let value = ((self.next_u64() as u128) << 64) | self.next_u64() as u128;
+            low + value % (high - low + 1)
+        }
+
+        // Amounts of every magnitude, from dust to pools near the top of u128
+        fn amount(&mut self) -> u128 {
+            let bits = self.between(1, 100) as u32;
+            self.between(1, (1u128 << bits) - 1)
+        }
+
+        // A valid payout schedule with up to `places` places
+        fn schedule(&mut self, places: usize) -> Vec<u32> {
+            let places = self.between(1, places as u128) as usize;
+            let mut cuts: Vec<u32> = (1..places)
+                .map(|_| self.between(1, BASIS_POINTS as u128 - 1) as u32)
+                .collect();
+            cuts.push(0);
+            cuts.push(BASIS_POINTS);
+            cuts.sort_unstable();
+            cuts.dedup();
+            cuts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+        }
+    }
+
+    #[test]
+    fn mul_div_rounds_down_and_never_exceeds_the_value() {
+        let mut rng = Rng(0x5eed_0001);
+        for _ in 0..ROUNDS {
+            let value = rng.amount();
+            let denominator = rng.amount();
+            let numerator = rng.between(0, denominator);
+
+            let result = mul_div(value, numerator, denominator);
+            assert!(result <= value);
+            let product = U256::from(value) * U256::from(numerator);
+            assert!(U256::from(result) * U256::from(denominator) <= product);
+            assert!((U256::from(result) + 1) * U256::from(denominator) > product);
+        }
+    }
+
+    #[test]
+    fn split_by_weights_never_pays_more_than_the_amount() {
+        let mut rng = Rng(0x5eed_0002);
+        for _ in 0..ROUNDS {
+            let amount = rng.amount();
+            let weights: Vec<u128> = (0..rng.between(1, 10)).map(|_| rng.amount()).collect();
+
+            let parts = split_by_weights(amount, &weights);
+            let total = parts.iter().sum::<u128>();
+            assert!(total <= amount);
+            // Every part loses less than one unit to rounding
+            assert!(amount - total < weights.len() as u128);
+        }
+    }
+
+    #[test]
+    fn schedules_never_pay_more_than_the_pool() {
+        let mut rng = Rng(0x5eed_0003);
+        for _ in 0..ROUNDS {
+            let schedule = rng.schedule(10);
+            assert_valid_schedule(&schedule);
+
+            let distributable = rng.amount();
+            let places: Vec<u128> = schedule
+                .iter()
+                .map(|basis_points| apply_basis_points(distributable, *basis_points))
+                .collect();
+            assert!(places.iter().sum::<u128>() <= distributable);
+        }
+    }
+
+    #[test]
+    fn claims_pay_out_the_pool_exactly_in_any_order() {
+        let mut rng = Rng(0x5eed_0004);
+        for _ in 0..ROUNDS {
+            let distributable = rng.amount();
+            let stakes: Vec<u128> = (0..rng.between(1, 20)).map(|_| rng.amount()).collect();
+            let total_winning_stake = stakes.iter().sum::<u128>();
+
+            // Winners claim in a random order
+            let mut order: Vec<usize> = (0..stakes.len()).collect();
+            for index in (1..order.len()).rev() {
+                order.swap(index, rng.between(0, index as u128) as usize);
+            }
+
+            let (mut claimed_stake, mut total_paid) = (0u128, 0u128);
+            for (count, index) in order.iter().enumerate() {
+                let stake = stakes[*index];
+                let share = pro_rata_share(distributable, stake, total_winning_stake);
+                let amount = claim_share(
+                    distributable,
+                    share,
+                    stake,
+                    total_winning_stake,
+                    claimed_stake,
+                    total_paid,
+                );
+
+                if count + 1 < order.len() {
+                    assert_eq!(amount, share);
+                } else {
+                    // The last claimant takes the dust everyone before them left behind
+                    assert!(amount >= share);
+                    assert!(amount - share < order.len() as u128);
+                }
+                claimed_stake += stake;
+                total_paid += amount;
+                assert!(total_paid <= distributable);
+            }
+            assert_eq!(total_paid, distributable);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState};
+use crate::payout;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method anyone can call to push a challenge whose phase has expired into its next
+    // phase. Returns the state the challenge is in afterwards.
+    pub fn advance_challenge(&mut self, challenge_id: u32) -> ChallengeState {
+        let challenge = self.internal_get_challenge(challenge_id);
+        let now = env::block_timestamp();
+
+        match challenge.state {
+            // Nobody accepted in time
+            ChallengeState::Pending if now >= challenge.acceptance_deadline.0 => {
+                self.internal_cancel_challenge(challenge_id)
+            }
+            // The game is over, no more bets
+            ChallengeState::Accepted | ChallengeState::Ongoing
+                if Challenge::has_passed(challenge.betting_closes_at, now) =>
+            {
+                self.update_set_challenge_state(challenge_id, ChallengeState::Voting)
+            }
+            // The oracle never reported, nobody else can decide the result
+            ChallengeState::Voting
+                if challenge.oracle.is_some()
+                    && Challenge::has_passed(challenge.voting_closes_at, now) =>
+            {
+                self.internal_cancel_challenge(challenge_id)
+            }
+            // Decide with the votes that were cast in time
+            ChallengeState::Voting if Challenge::has_passed(challenge.voting_closes_at, now) => {
+                self.internal_finish_voting(challenge_id)
+            }
+            // Nobody can be paid, so nothing may go to the protocol either
+            ChallengeState::VotingFinished if self.internal_has_no_backed_place(&challenge) => {
+                self.internal_cancel_challenge(challenge_id)
+            }
+            // The arbiter did not rule in time
+            ChallengeState::Disputed if Self::is_dispute_expired(&challenge, now) => {
+                self.internal_expire_dispute(challenge_id)
+            }
+            // Claims can only close once they have been open
+            ChallengeState::VotingFinished | ChallengeState::Claim
+                if Self::is_settleable(&challenge)
+                    && Challenge::has_passed(challenge.claim_expires_at, now) =>
+            {
+                self.internal_close_claims(challenge_id)
+            }
+            _ => env::panic_str(&format!(
+                "Challenge {} has no expired phase to advance from {:?}",
+                challenge_id, challenge.state
+            )),
+        }
+
+        self.internal_get_challenge(challenge_id).state
+    }
+
+    // End the claim window: every winner that has not claimed gets their share as a
+    // pending refund, to be collected with `retry_refund`
+    fn internal_close_claims(&mut self, challenge_id: u32) {
+        let challenge = self.internal_get_challenge(challenge_id);
+        if challenge.settlement.is_none() {
+            let fee = self.internal_protocol_fee(&challenge);
+            let places = self.internal_places(&challenge, challenge.total_pool() - fee);
+            self.internal_open_settlement(challenge_id, places);
+        }
+
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        let mut settlement = challenge
+            .settlement
+            .clone()
+            .expect("Challenge has not been settled");
+        let distributable = settlement.total_pool.0 - settlement.fee.0;
+        let total_winning_stake = challenge.total_staked_on(&settlement.winners());
+        let accounts: Vec<AccountId> = self
+            .bets
+            .get(&challenge_id)
+            .map_or(Vec::new(), |self_map| self_map.keys().collect());
+
+        // Shared out like claims in bet order, so the last one takes the dust
+        for account_id in accounts {
+            let (stake, share) =
+                self.internal_account_share(&challenge, &settlement.places, &account_id);
+            if stake == 0 || !self.internal_mark_claimed(challenge_id, &account_id) {
+                continue;
+            }
+            let amount = payout::claim_share(
+                distributable,
+                share,
+                stake,
+                total_winning_stake,
+                settlement.claimed_stake.0,
+                settlement.total_paid.0,
+            );
+            settlement.total_paid = U128(settlement.total_paid.0.saturating_add(amount));
+            settlement.claimed_stake = U128(settlement.claimed_stake.0.saturating_add(stake));
+            if amount > 0 {
+                // Still owed, now as a refund the winner can collect at any time
+                self.internal_record_received(&account_id, challenge_id, amount, true);
+                self.internal_add_pending_refund(&account_id, &challenge.token, amount);
+            }
+        }
+
+        challenge.settlement = Some(settlement);
+        self.challenges.insert(&challenge_id, &challenge);
+        self.update_set_challenge_state(challenge_id, ChallengeState::Closed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+
+    use crate::challenge::{ChallengeState, MIN_CLAIM_WINDOW};
+    use crate::test_utils::*;
+
+    const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+    // Three players staking 100 each, claims expiring after four days
+    fn decided_late() -> crate::ArenaProtocolContract {
+        let mut contract = contract();
+        let players = [alice(), bob(), charlie()];
+        let extra = format!(r#","claim_expires_at":"{}""#, 4 * DAY);
+        live_challenge(&mut contract, &players, 100, &extra);
+
+        // Nobody votes before the claim deadline
+        context(alice(), 5 * DAY);
+        for player in players {
+            vote(&mut contract, player, 1, vec![alice()]);
+        }
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "The claim window has to outlast the dispute period")]
+    fn claim_window_must_be_long_enough() {
+        let mut contract = contract();
+        let extra = format!(r#","claim_expires_at":"{}""#, DAY);
+        create_challenge(&mut contract, &[alice(), bob()], 100, &extra);
+    }
+
+    #[test]
+    fn claims_open_when_voting_finishes_after_the_claim_deadline() {
+        let mut contract = decided_late();
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.state, ChallengeState::VotingFinished);
+        assert_eq!(
+            challenge.claim_expires_at.unwrap().0,
+            5 * DAY + MIN_CLAIM_WINDOW
+        );
+
+        context(alice(), 5 * DAY + 1);
+        assert_eq!(contract.claim(1), U128(293));
+        assert_eq!(contract.get_protocol_fees(None).accrued.0, 7);
+    }
+
+    #[test]
+    fn unclaimed_winnings_become_pending_refunds() {
+        let mut contract = decided_late();
+
+        context(bob(), 5 * DAY + MIN_CLAIM_WINDOW);
+        assert_eq!(contract.advance_challenge(1), ChallengeState::Closed);
+
+        // Only the fee goes to the protocol, the winner can still collect the rest
+        assert_eq!(contract.get_protocol_fees(None).accrued.0, 7);
+        assert_eq!(contract.get_pending_refund(alice(), None), U128(293));
+        assert_eq!(contract.get_total_owed(None).0, 300);
+        let settlement = contract
+            .get_challenge_details(1)
+            .unwrap()
+            .settlement
+            .unwrap();
+        assert_eq!(settlement.total_paid, U128(293));
+    }
+}
@@ -0,0 +1,300 @@
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::{I128, U128};
+use near_sdk::{env, near, near_bindgen, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState};
+use crate::index::{account_prefix, DEFAULT_PAGE_LIMIT};
+use crate::payout;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// What an account bet on one participant of a challenge
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionSide {
+    pub participant: AccountId,
+    pub amount: U128,
+}
+
+// Everything an account has at stake in one challenge
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    pub challenge_id: u32,
+    // Token the stake and every amount below are in
+    pub token_id: AccountId,
+    pub state: ChallengeState,
+    pub sides: Vec<PositionSide>,
+    // Bet on any side, including the acceptance stake of a participant
+    pub stake: U128,
+    // Whether the account plays in the challenge, as a participant or a team member
+    pub is_participant: bool,
+    // What `claim` would pay right now
+    pub claimable: U128,
+    // Winnings and refunds sent to the account so far, or kept for it as pending refunds
+    pub received: U128,
+    // `received` minus `stake`, once nothing can change the position any more
+    pub realized_pnl: Option<I128>,
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method to list every challenge an account has bet on, oldest first
+    pub fn get_account_portfolio(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<Position> {
+        let Some(positions) = self.account_positions.get(&account_id) else {
+            return Vec::new();
+        };
+        positions
+            .keys_as_vector()
+            .iter()
+            .zip(positions.values_as_vector().iter())
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .filter_map(|(challenge_id, received)| {
+                let challenge = self.challenges.get(&challenge_id)?;
+                Some(self.internal_position(&challenge, &account_id, received))
+            })
+            .collect()
+    }
+
+    // Add a challenge to the portfolio of an account that bet on it
+    pub(crate) fn internal_track_position(&mut self, account_id: &AccountId, challenge_id: u32) {
+        let mut positions = self.internal_positions(account_id);
+        if positions.get(&challenge_id).is_none() {
+            positions.insert(&challenge_id, &0);
+            self.account_positions.insert(account_id, &positions);
+        }
+    }
+
+    // Keep track of what was paid back to an account for a challenge. Transfers that
+    // fail and can be claimed again are taken back off with `paid` set to false.
+    pub(crate) fn internal_record_received(
+        &mut self,
+        account_id: &AccountId,
+        challenge_id: u32,
+        amount: u128,
+        paid: bool,
+    ) {
+        let mut positions = self.internal_positions(account_id);
+        let received = positions.get(&challenge_id).unwrap_or(0);
+        let received = if paid {
+            received.saturating_add(amount)
+        } else {
+            received.saturating_sub(amount)
+        };
+        positions.insert(&challenge_id, &received);
+        self.account_positions.insert(account_id, &positions);
+    }
+
+    // What was paid back to an account for a challenge so far
+    pub(crate) fn internal_received(&self, account_id: &AccountId, challenge_id: u32) -> u128 {
+        self.account_positions
+            .get(account_id)
+            .and_then(|positions| positions.get(&challenge_id))
+            .unwrap_or(0)
+    }
+
+    fn internal_positions(&self, account_id: &AccountId) -> UnorderedMap<u32, u128> {
+        self.account_positions
+            .get(account_id)
+            .unwrap_or_else(|| UnorderedMap::new(account_prefix(b'h', account_id)))
+    }
+
+    fn internal_position(
+        &self,
+        challenge: &Challenge,
+        account_id: &AccountId,
+        received: u128,
+    ) -> Position {
+        let sides: Vec<PositionSide> = self
+            .bets
+            .get(&challenge.id)
+            .and_then(|self_map| self_map.get(account_id))
+            .map_or(Vec::new(), |account_bets| {
+                account_bets
+                    .iter()
+                    .map(|(participant, amount)| PositionSide {
+                        participant,
+                        amount: U128(amount),
+                    })
+                    .collect()
+            });
+        let stake = sides
+            .iter()
+            .fold(0u128, |total, side| total.saturating_add(side.amount.0));
+
+        let (winning_stake, claimable) = self.internal_claimable(challenge, account_id);
+        let settled = match challenge.state {
+            ChallengeState::Cancelled => {
+                stake == 0 || self.has_claimed(challenge.id, account_id.clone())
+            }
+            ChallengeState::Closed => true,
+            _ => {
+                Self::is_settleable(challenge)
+                    && (winning_stake == 0 || self.has_claimed(challenge.id, account_id.clone()))
+            }
+        };
+
+        Position {
+            challenge_id: challenge.id,
+            token_id: challenge.token.clone(),
+            state: challenge.state,
+            sides,
+            stake: U128(stake),
+            is_participant: challenge.side_of(account_id).is_some(),
+            claimable: U128(claimable),
+            received: U128(received),
+            realized_pnl: settled.then(|| I128(received as i128 - stake as i128)),
+        }
+    }
+
+    // Stake of the account on the paid places, and what claiming it would pay right now.
+    // Mirrors `internal_settle_account`, including the dust for the last claimant, and
+    // `claim_refund` for a cancelled challenge.
+    pub(crate) fn internal_claimable(
+        &self,
+        challenge: &Challenge,
+        account_id: &AccountId,
+    ) -> (u128, u128) {
+        // The stakes of a cancelled challenge are refunded in full
+        if challenge.state == ChallengeState::Cancelled {
+            if self.has_claimed(challenge.id, account_id.clone()) {
+                return (0, 0);
+            }
+            return (0, self.internal_stake_of(challenge.id, account_id));
+        }
+        if !Self::is_settleable(challenge) {
+            return (0, 0);
+        }
+
+        let fee = self.internal_protocol_fee(challenge);
+        let distributable = challenge.total_pool() - fee;
+        let (places, claimed_stake, total_paid) = match &challenge.settlement {
+            Some(settlement) => (
+                settlement.places.clone(),
+                settlement.claimed_stake.0,
+                settlement.total_paid.0,
+            ),
+            None => (self.internal_places(challenge, distributable), 0, 0),
+        };
+
+        let (stake, share) = self.internal_account_share(challenge, &places, account_id);
+        if stake == 0
+            || self.has_claimed(challenge.id, account_id.clone())
+            || Challenge::has_passed(challenge.claim_expires_at, env::block_timestamp())
+        {
+            return (stake, 0);
+        }
+
+        let winners: Vec<AccountId> = places
+            .iter()
+            .map(|place| place.participant.clone())
+            .collect();
+        let amount = payout::claim_share(
+            distributable,
+            share,
+            stake,
+            challenge.total_staked_on(&winners),
+            claimed_stake,
+            total_paid,
+        );
+        (stake, amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::{I128, U128};
+
+    use crate::challenge::ChallengeState;
+    use crate::test_utils::*;
+
+    // Alice and bob play for 100 each and dave bets 50 on alice, alice wins
+    fn decided() -> crate::ArenaProtocolContract {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, dave(), 1, alice(), 50);
+        vote(&mut contract, alice(), 1, vec![alice()]);
+        vote(&mut contract, bob(), 1, vec![alice()]);
+        contract
+    }
+
+    #[test]
+    fn open_position_shows_the_stake_and_no_pnl() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, dave(), 1, alice(), 50);
+
+        let portfolio = contract.get_account_portfolio(dave(), None, None);
+        assert_eq!(portfolio.len(), 1);
+        let position = &portfolio[0];
+        assert_eq!(position.state, ChallengeState::Ongoing);
+        assert_eq!(position.token_id, token());
+        assert_eq!(position.stake, U128(50));
+        assert!(!position.is_participant);
+        assert_eq!(position.claimable, U128(0));
+        assert_eq!(position.realized_pnl, None);
+        assert!(contract
+            .get_account_portfolio(charlie(), None, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn claim_realizes_the_pnl_of_a_winner() {
+        let mut contract = decided();
+        // 250 staked, 6 in fees, 244 shared by 150 on alice
+        let position = contract
+            .get_account_portfolio(alice(), None, None)
+            .remove(0);
+        assert_eq!(position.claimable, U128(162));
+        assert_eq!(position.realized_pnl, None);
+
+        context(alice(), 1);
+        contract.claim(1);
+        let position = contract
+            .get_account_portfolio(alice(), None, None)
+            .remove(0);
+        assert!(position.is_participant);
+        assert_eq!(position.claimable, U128(0));
+        assert_eq!(position.received, U128(162));
+        assert_eq!(position.realized_pnl, Some(I128(62)));
+
+        // The loser's position is final as soon as the challenge is decided
+        let position = contract.get_account_portfolio(bob(), None, None).remove(0);
+        assert_eq!(position.realized_pnl, Some(I128(-100)));
+    }
+
+    #[test]
+    fn refunded_stake_breaks_even() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        context(alice(), 1);
+        contract.cancel_challenge(1);
+
+        let position = contract
+            .get_account_portfolio(alice(), None, None)
+            .remove(0);
+        assert_eq!(position.state, ChallengeState::Cancelled);
+        assert_eq!(position.received, U128(100));
+        assert_eq!(position.realized_pnl, Some(I128(0)));
+    }
+
+    #[test]
+    fn portfolio_is_paginated_oldest_first() {
+        let mut contract = contract();
+        for _ in 0..3 {
+            create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        }
+        let page: Vec<u32> = contract
+            .get_account_portfolio(alice(), Some(1), Some(1))
+            .iter()
+            .map(|position| position.challenge_id)
+            .collect();
+        assert_eq!(page, vec![2]);
+    }
+}
@@ -0,0 +1,314 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId, NearToken, Promise, PromiseResult};
+
+use crate::arena::{ext_ft_contract, MIN_GAS_FOR_FT_TRANSFER};
+use crate::challenge::{ChallengeState, VotingOutcome};
+use crate::events::ArenaEvent;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// After a week without bets, votes or state changes any bettor may cancel a challenge
+// whose phase deadlines have all passed
+pub const CHALLENGE_INACTIVITY_TIMEOUT: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+// Refunds a cancel pushes at most, so cancelling never runs out of gas. Bettors past
+// the first ones pull their stake with `claim_refund`.
+pub const MAX_REFUNDS_PER_CANCEL: usize = 10;
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method to cancel a challenge and refund the stakes of its bettors.
+    // The creator can cancel while the challenge is still Pending, anyone can once a
+    // Pending challenge is past its acceptance deadline or once a decided challenge turns
+    // out to have no backed participant to pay, and any bettor can once the challenge has
+    // been inactive for CHALLENGE_INACTIVITY_TIMEOUT past its phase deadlines.
+    pub fn cancel_challenge(&mut self, challenge_id: u32) {
+        let account_id = env::predecessor_account_id();
+        let challenge = self.internal_get_challenge(challenge_id);
+
+        let is_creator_cancel =
+            challenge.state == ChallengeState::Pending && account_id == challenge.creator;
+        // Nobody accepted in time, the escrowed stakes go back to their owners
+        let is_expired_cancel = challenge.state == ChallengeState::Pending
+            && env::block_timestamp() >= challenge.acceptance_deadline.0;
+        // A quiet phase that still has time left on its deadline is not abandoned
+        let is_timeout_cancel = self.internal_stake_of(challenge_id, &account_id) > 0
+            && challenge.phase_deadlines_passed(env::block_timestamp())
+            && env::block_timestamp()
+                >= challenge
+                    .updated_at
+                    .0
+                    .saturating_add(CHALLENGE_INACTIVITY_TIMEOUT);
+        // The winner was decided, but nobody bet on any ranked participant
+        let is_unbacked_cancel = self.internal_has_no_backed_place(&challenge);
+        require!(
+            !matches!(challenge.outcome, Some(VotingOutcome::Decided(_))) || is_unbacked_cancel,
+            "Cannot cancel a challenge that has a winner"
+        );
+        require!(
+            is_creator_cancel || is_expired_cancel || is_timeout_cancel || is_unbacked_cancel,
+            "Only the creator of a pending challenge, anyone after the acceptance deadline or once nobody can be paid, or a bettor after the inactivity timeout, can cancel it"
+        );
+
+        self.internal_cancel_challenge(challenge_id);
+    }
+
+    // Method for a bettor to take their stake back from a cancelled challenge that had
+    // too many bettors to refund them all when it was cancelled
+    pub fn claim_refund(&mut self, challenge_id: u32) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let challenge = self.internal_get_challenge(challenge_id);
+
+        Self::assert_challenge_state(
+            &challenge,
+            &[ChallengeState::Cancelled],
+            "Only the stakes of a cancelled challenge can be refunded",
+        );
+        require!(
+            self.internal_stake_of(challenge_id, &account_id) > 0,
+            "Nothing to refund for this account"
+        );
+        self.internal_refund_stake(challenge_id, &challenge.token, &account_id)
+            .expect("The stake has already been refunded")
+    }
+
+    // Method to retry the refunds of the caller that failed earlier, in one token,
+    // the default token if none is given
+    pub fn retry_refund(&mut self, token_id: Option<AccountId>) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let token_id = self.internal_token_or_default(token_id);
+        let amount = self
+            .pending_refunds
+            .remove(&(account_id.clone(), token_id.clone()))
+            .expect("No pending refund for this account");
+
+        // Refunds that are retried are no longer tied to a single challenge
+        self.internal_refund(None, token_id, account_id, amount)
+    }
+
+    // Method to get the amount of failed refunds owed to an account in a token,
+    // the default token if none is given
+    pub fn get_pending_refund(&self, account_id: AccountId, token_id: Option<AccountId>) -> U128 {
+        let token_id = self.internal_token_or_default(token_id);
+        U128(
+            self.pending_refunds
+                .get(&(account_id, token_id))
+                .unwrap_or(0),
+        )
+    }
+
+    #[private]
+    pub fn on_refund_transfer(
+        &mut self,
+        challenge_id: Option<u32>,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: U128,
+    ) -> bool {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                self.internal_sub_owed(&token_id, amount.0);
+                ArenaEvent::Refund {
+                    challenge_id,
+                    token_id,
+                    account_id,
+                    amount,
+                    success: true,
+                }
+                .emit();
+                true
+            }
+            PromiseResult::Failed => {
+                // Still owed, the account can call retry_refund later
+                self.internal_add_pending_refund(&account_id, &token_id, amount.0);
+
+                ArenaEvent::Refund {
+                    challenge_id,
+                    token_id,
+                    account_id,
+                    amount,
+                    success: false,
+                }
+                .emit();
+                false
+            }
+        }
+    }
+
+    // Move a challenge to Cancelled and refund its bettors, in bet order. A refund that
+    // fails is kept as a pending refund for `retry_refund`.
+    pub(crate) fn internal_cancel_challenge(&mut self, challenge_id: u32) {
+        self.update_set_challenge_state(challenge_id, ChallengeState::Cancelled);
+
+        let token = self.internal_get_challenge(challenge_id).token;
+        let accounts: Vec<AccountId> =
+            self.bets.get(&challenge_id).map_or(Vec::new(), |self_map| {
+                self_map.keys().take(MAX_REFUNDS_PER_CANCEL).collect()
+            });
+        for account_id in accounts {
+            self.internal_refund_stake(challenge_id, &token, &account_id);
+        }
+    }
+
+    // Send one bettor their whole stake in a cancelled challenge. Returns None if there
+    // is nothing to refund or it was already refunded.
+    fn internal_refund_stake(
+        &mut self,
+        challenge_id: u32,
+        token_id: &AccountId,
+        account_id: &AccountId,
+    ) -> Option<Promise> {
+        let amount = self.internal_stake_of(challenge_id, account_id);
+        // A cancelled challenge was never paid out, so its claimed set tracks the refunds
+        if amount == 0 || !self.internal_mark_claimed(challenge_id, account_id) {
+            return None;
+        }
+        self.internal_record_received(account_id, challenge_id, amount, true);
+        Some(self.internal_refund(
+            Some(challenge_id),
+            token_id.clone(),
+            account_id.clone(),
+            amount,
+        ))
+    }
+
+    pub(crate) fn internal_refund(
+        &mut self,
+        challenge_id: Option<u32>,
+        token_id: AccountId,
+        account_id: AccountId,
+        amount: u128,
+    ) -> Promise {
+        // Stays owed until the callback sees the transfer succeed
+        ext_ft_contract::ext(token_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(account_id.clone(), U128(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(MIN_GAS_FOR_FT_TRANSFER)
+                    .on_refund_transfer(challenge_id, token_id, account_id, U128(amount)),
+            )
+    }
+
+    // Total stake of one bettor in a challenge, across all participants
+    pub(crate) fn internal_stake_of(&self, challenge_id: u32, account_id: &AccountId) -> u128 {
+        self.bets
+            .get(&challenge_id)
+            .and_then(|self_map| self_map.get(account_id))
+            .map_or(0, |amount_map| {
+                amount_map
+                    .values()
+                    .fold(0u128, |total, amount| total.saturating_add(amount))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::{AccountId, PromiseResult};
+
+    use super::{CHALLENGE_INACTIVITY_TIMEOUT, MAX_REFUNDS_PER_CANCEL};
+    use crate::challenge::ChallengeState;
+    use crate::test_utils::*;
+    use crate::ArenaProtocolContract;
+
+    // Alice and bob play for 100 each, dave bets 50 on bob
+    fn contract_with_bets() -> ArenaProtocolContract {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, dave(), 1, bob(), 50);
+        contract
+    }
+
+    #[test]
+    fn creator_cancel_refunds_the_creator() {
+        let mut contract = contract();
+        create_challenge(&mut contract, &[alice(), bob()], 100, "");
+        context(alice(), 1);
+        contract.cancel_challenge(1);
+
+        assert_eq!(contract.get_challenge(1), Some(ChallengeState::Cancelled));
+        assert!(contract.has_claimed(1, alice()));
+        callback_context(PromiseResult::Successful(vec![]));
+        assert!(contract.on_refund_transfer(Some(1), token(), alice(), U128(100)));
+        assert_eq!(contract.get_total_owed(None), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "can cancel it")]
+    fn bettor_cannot_cancel_before_the_inactivity_timeout() {
+        let mut contract = contract_with_bets();
+        context(dave(), CHALLENGE_INACTIVITY_TIMEOUT - 1);
+        contract.cancel_challenge(1);
+    }
+
+    #[test]
+    fn inactivity_cancel_refunds_every_bettor() {
+        let mut contract = contract_with_bets();
+        context(dave(), CHALLENGE_INACTIVITY_TIMEOUT);
+        contract.cancel_challenge(1);
+
+        for account_id in [alice(), bob(), dave()] {
+            assert!(contract.has_claimed(1, account_id));
+        }
+        let position = &contract.get_account_portfolio(dave(), None, None)[0];
+        assert_eq!(position.received, U128(50));
+        assert_eq!(position.claimable, U128(0));
+        // Owed until the transfers are confirmed
+        assert_eq!(contract.get_total_owed(None), U128(250));
+    }
+
+    #[test]
+    fn failed_refund_can_be_retried() {
+        let mut contract = contract_with_bets();
+        context(dave(), CHALLENGE_INACTIVITY_TIMEOUT);
+        contract.cancel_challenge(1);
+
+        callback_context(PromiseResult::Failed);
+        assert!(!contract.on_refund_transfer(Some(1), token(), dave(), U128(50)));
+        assert_eq!(contract.get_pending_refund(dave(), None), U128(50));
+        assert_eq!(contract.get_total_owed(None), U128(250));
+
+        context(dave(), CHALLENGE_INACTIVITY_TIMEOUT);
+        contract.retry_refund(None);
+        assert_eq!(contract.get_pending_refund(dave(), None), U128(0));
+        callback_context(PromiseResult::Successful(vec![]));
+        assert!(contract.on_refund_transfer(None, token(), dave(), U128(50)));
+        assert_eq!(contract.get_total_owed(None), U128(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "The stake has already been refunded")]
+    fn refund_is_only_paid_once() {
+        let mut contract = contract_with_bets();
+        context(dave(), CHALLENGE_INACTIVITY_TIMEOUT);
+        contract.cancel_challenge(1);
+        contract.claim_refund(1);
+    }
+
+    #[test]
+    fn bettors_past_the_pushed_refunds_claim_theirs() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        let bettors: Vec<AccountId> = (0..MAX_REFUNDS_PER_CANCEL)
+            .map(|index| format!("bettor{}.near", index).parse().unwrap())
+            .collect();
+        for bettor in &bettors {
+            bet(&mut contract, bettor.clone(), 1, alice(), 10);
+        }
+        context(alice(), CHALLENGE_INACTIVITY_TIMEOUT);
+        contract.cancel_challenge(1);
+
+        // The players and the first bettors were refunded, the last bettor was not
+        let last = bettors.last().unwrap().clone();
+        assert!(contract.has_claimed(1, bettors[0].clone()));
+        assert!(!contract.has_claimed(1, last.clone()));
+        let position = &contract.get_account_portfolio(last.clone(), None, None)[0];
+        assert_eq!(position.claimable, U128(10));
+
+        context(last.clone(), 1);
+        contract.claim_refund(1);
+        assert!(contract.has_claimed(1, last));
+    }
+}
@@ -0,0 +1,373 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, near, near_bindgen, require, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState, PlacePayout, VotingOutcome};
+use crate::payout;
+use crate::voting::Standing;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// What a single account receives from a settled pool
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountPayout {
+    pub account_id: AccountId,
+    // Total the account bet on the winners
+    pub winning_stake: U128,
+    pub amount: U128,
+}
+
+// How the pool of a challenge is split, shared by the preview and the payout paths
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettlementPlan {
+    pub challenge_id: u32,
+    pub winners: Vec<AccountId>,
+    pub places: Vec<PlacePayout>,
+    pub total_pool: U128,
+    pub fee: U128,
+    // Pool left after the fee
+    pub distributable: U128,
+    // One entry per bettor, in bet order, 0 for losses. Accounts that have claimed show
+    // what they were paid, the others what they would be paid claiming in this order.
+    pub payouts: Vec<AccountPayout>,
+    // Rounding remainder of the shares, paid to the last winning account to claim
+    pub dust: U128,
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method to preview how the pool of a challenge is split
+    pub fn get_settlement_plan(&self, challenge_id: u32) -> SettlementPlan {
+        self.internal_settlement_plan(challenge_id)
+    }
+
+    // The settlement engine: paid places, pool, fee, and the payout of every bettor.
+    // Once a challenge has been settled its places, fee and payouts so far are fixed, so
+    // the plan keeps matching what was actually paid.
+    pub(crate) fn internal_settlement_plan(&self, challenge_id: u32) -> SettlementPlan {
+        let challenge = self.internal_get_challenge(challenge_id);
+        Self::assert_settleable(&challenge);
+
+        let total_pool = challenge.total_pool();
+        require!(total_pool > 0, "Invalid total pool");
+        let fee = self.internal_protocol_fee(&challenge);
+        let distributable = total_pool - fee;
+
+        let (places, mut claimed_stake, mut total_paid) = match &challenge.settlement {
+            Some(settlement) => (
+                settlement.places.clone(),
+                settlement.claimed_stake.0,
+                settlement.total_paid.0,
+            ),
+            None => (self.internal_places(&challenge, distributable), 0, 0),
+        };
+        let winners: Vec<AccountId> = places
+            .iter()
+            .map(|place| place.participant.clone())
+            .collect();
+        let total_winning_stake = challenge.total_staked_on(&winners);
+
+        let challenge_bets = self
+            .bets
+            .get(&challenge_id)
+            .expect("Challenge bets not found");
+        let mut total_shares = 0u128;
+        let mut payouts = Vec::new();
+        for account_id in challenge_bets.keys() {
+            // Stake and rounded down share of the bettor on the paid places (0 for losses)
+            let (stake, share) = self.internal_account_share(&challenge, &places, &account_id);
+            total_shares = total_shares.saturating_add(share);
+
+            // The same rule as `claim`: the last account to claim receives the dust
+            let amount = if stake == 0 {
+                0
+            } else if self.has_claimed(challenge_id, account_id.clone()) {
+                self.internal_received(&account_id, challenge_id)
+            } else {
+                let amount = payout::claim_share(
+                    distributable,
+                    share,
+                    stake,
+                    total_winning_stake,
+                    claimed_stake,
+                    total_paid,
+                );
+                claimed_stake = claimed_stake.saturating_add(stake);
+                total_paid = total_paid.saturating_add(amount);
+                amount
+            };
+
+            payouts.push(AccountPayout {
+                account_id,
+                winning_stake: U128(stake),
+                amount: U128(amount),
+            });
+        }
+        let dust = if total_winning_stake > 0 {
+            distributable.saturating_sub(total_shares)
+        } else {
+            0
+        };
+
+        SettlementPlan {
+            challenge_id,
+            winners,
+            places,
+            total_pool: U128(total_pool),
+            fee: U128(fee),
+            distributable: U128(distributable),
+            payouts,
+            dust: U128(dust),
+        }
+    }
+
+    // Apply the payout schedule of the challenge to its final standings.
+    // Tied participants pool the places they cover and share them equally. Places
+    // nobody can be paid for, because nobody was ranked there or nobody bet on the
+    // participant, are spread over the other places in proportion to their share.
+    // Empty if none of the ranked participants was backed.
+    pub(crate) fn internal_places(
+        &self,
+        challenge: &Challenge,
+        distributable: u128,
+    ) -> Vec<PlacePayout> {
+        let standings = self.internal_standings(challenge);
+        require!(
+            !standings.is_empty(),
+            "Winners for this challenge not found"
+        );
+
+        // Participants sharing a position, with the share of the places they cover
+        let mut groups: Vec<(u8, u128, Vec<AccountId>)> = Vec::new();
+        let mut index = 0;
+        while index < standings.len() {
+            let position = standings[index].position;
+            let tied: Vec<AccountId> = standings[index..]
+                .iter()
+                .take_while(|standing| standing.position == position)
+                .map(|standing| standing.participant.clone())
+                .collect();
+            let basis_points: u128 = challenge
+                .payout_schedule
+                .iter()
+                .skip(index)
+                .take(tied.len())
+                .map(|basis_points| *basis_points as u128)
+                .sum();
+            index += tied.len();
+
+            let backed: Vec<AccountId> = tied
+                .into_iter()
+                .filter(|participant| {
+                    challenge.total_staked_on(std::slice::from_ref(participant)) > 0
+                })
+                .collect();
+            if basis_points > 0 && !backed.is_empty() {
+                groups.push((position, basis_points, backed));
+            }
+        }
+
+        // Nobody backed a paid place: the best ranked participants that were backed take
+        // the whole pool. If no ranked participant was backed the stakes are refunded.
+        if groups.is_empty() {
+            let is_backed = |standing: &&Standing| {
+                challenge.total_staked_on(std::slice::from_ref(&standing.participant)) > 0
+            };
+            if let Some(position) = standings.iter().find(is_backed).map(|s| s.position) {
+                let backed: Vec<AccountId> = standings
+                    .iter()
+                    .filter(|standing| standing.position == position)
+                    .filter(is_backed)
+                    .map(|standing| standing.participant.clone())
+                    .collect();
+                groups.push((position, payout::BASIS_POINTS as u128, backed));
+            }
+        }
+
+        let weights: Vec<u128> = groups.iter().map(|(_, weight, _)| *weight).collect();
+        let amounts = payout::split_by_weights(distributable, &weights);
+
+        let mut places = Vec::new();
+        for ((position, _, participants), amount) in groups.into_iter().zip(amounts) {
+            let each = amount / participants.len() as u128;
+            for participant in participants {
+                places.push(PlacePayout {
+                    participant,
+                    position,
+                    amount: U128(each),
+                });
+            }
+        }
+        places
+    }
+
+    // What an account bet on the paid places, and its rounded down share of them
+    pub(crate) fn internal_account_share(
+        &self,
+        challenge: &Challenge,
+        places: &[PlacePayout],
+        account_id: &AccountId,
+    ) -> (u128, u128) {
+        let Some(account_bets) = self
+            .bets
+            .get(&challenge.id)
+            .and_then(|self_map| self_map.get(account_id))
+        else {
+            return (0, 0);
+        };
+
+        let mut stake = 0u128;
+        let mut share = 0u128;
+        for place in places {
+            let bet = account_bets.get(&place.participant).unwrap_or(0);
+            if bet > 0 {
+                stake = stake.saturating_add(bet);
+                share = share.saturating_add(payout::pro_rata_share(
+                    place.amount.0,
+                    bet,
+                    challenge.total_staked_on(std::slice::from_ref(&place.participant)),
+                ));
+            }
+        }
+        (stake, share)
+    }
+
+    // Whether the pool of a decided challenge has nobody to go to, so the stakes have to
+    // be refunded instead
+    pub(crate) fn internal_has_no_backed_place(&self, challenge: &Challenge) -> bool {
+        if !Self::is_settleable(challenge) || challenge.settlement.is_some() {
+            return false;
+        }
+        self.internal_standings(challenge).is_empty()
+            || self
+                .internal_places(
+                    challenge,
+                    challenge.total_pool() - self.internal_protocol_fee(challenge),
+                )
+                .is_empty()
+    }
+
+    // Whether `assert_settleable` would let the challenge be paid out
+    pub(crate) fn is_settleable(challenge: &Challenge) -> bool {
+        matches!(
+            challenge.state,
+            ChallengeState::VotingFinished | ChallengeState::Claim
+        ) && (challenge.state != ChallengeState::Claim || challenge.settlement.is_some())
+            && challenge.outcome != Some(VotingOutcome::NoConsensus)
+            && challenge
+                .dispute_deadline
+                .is_none_or(|deadline| env::block_timestamp() >= deadline.0)
+    }
+
+    // Pools can only be previewed and paid once voting has finished with a winner
+    pub(crate) fn assert_settleable(challenge: &Challenge) {
+        Self::assert_challenge_state(
+            challenge,
+            &[ChallengeState::VotingFinished, ChallengeState::Claim],
+            "Cannot settle a challenge before voting has finished",
+        );
+        // Every challenge moves to Claim along with its settlement, except the ones
+        // migrated from a version that did not record one
+        require!(
+            challenge.state != ChallengeState::Claim || challenge.settlement.is_some(),
+            "This challenge was paid out without a settlement record"
+        );
+        require!(
+            challenge.outcome != Some(VotingOutcome::NoConsensus),
+            "The voters did not agree on a winner, the challenge can only be replayed or cancelled"
+        );
+        require!(
+            challenge
+                .dispute_deadline
+                .is_none_or(|deadline| env::block_timestamp() >= deadline.0),
+            "The result of this challenge can still be disputed"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::AccountId;
+
+    use crate::test_utils::*;
+
+    const PAYOUT_SCHEDULE: &str = r#","payout_schedule":[7000,3000]"#;
+
+    // Three players staking 100 each, ranked by their votes
+    fn ranked(extra: &str, ballots: [Vec<AccountId>; 3]) -> crate::ArenaProtocolContract {
+        let mut contract = contract();
+        let players = [alice(), bob(), charlie()];
+        live_challenge(&mut contract, &players, 100, extra);
+        for (player, ballot) in players.into_iter().zip(ballots) {
+            vote(&mut contract, player, 1, ballot);
+        }
+        contract
+    }
+
+    fn places(contract: &crate::ArenaProtocolContract) -> Vec<(AccountId, u8, u128)> {
+        contract
+            .get_settlement_plan(1)
+            .places
+            .into_iter()
+            .map(|place| (place.participant, place.position, place.amount.0))
+            .collect()
+    }
+
+    #[test]
+    fn schedule_pays_the_first_two_places() {
+        let ranking = vec![alice(), bob(), charlie()];
+        let contract = ranked(PAYOUT_SCHEDULE, [ranking.clone(), ranking.clone(), ranking]);
+        assert_eq!(places(&contract), vec![(alice(), 1, 205), (bob(), 2, 87)]);
+
+        // The second place takes the dust as the last winner to claim
+        let plan = contract.get_settlement_plan(1);
+        let amounts: Vec<u128> = plan.payouts.iter().map(|payout| payout.amount.0).collect();
+        assert_eq!(amounts, vec![205, 88, 0]);
+        assert_eq!(plan.dust, U128(1));
+        assert_eq!(plan.distributable, U128(293));
+    }
+
+    #[test]
+    fn tied_participants_share_the_places_they_cover() {
+        let contract = ranked(
+            r#","payout_schedule":[6000,3000,1000]"#,
+            [
+                vec![alice(), bob(), charlie()],
+                vec![alice(), charlie(), bob()],
+                vec![alice()],
+            ],
+        );
+        assert_eq!(
+            places(&contract),
+            vec![(alice(), 1, 175), (bob(), 2, 58), (charlie(), 2, 58)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "A payout schedule has to add up to 100%")]
+    fn schedule_has_to_add_up_to_the_whole_pool() {
+        let mut contract = contract();
+        create_challenge(
+            &mut contract,
+            &[alice(), bob()],
+            100,
+            r#","payout_schedule":[7000,2000]"#,
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The payout schedule has more places than the challenge has participants"
+    )]
+    fn schedule_cannot_pay_more_places_than_participants() {
+        let mut contract = contract();
+        create_challenge(
+            &mut contract,
+            &[alice(), bob()],
+            100,
+            r#","payout_schedule":[5000,3000,2000]"#,
+        );
+    }
+}
@@ -0,0 +1,157 @@
+// Fixtures shared by the unit tests. Every call that goes through ft_on_transfer keeps
+// the block timestamp of the previous context, so a test moves time with `context`.
+use near_sdk::json_types::U128;
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{env, testing_env, AccountId, PromiseOrValue, PromiseResult, RuntimeFeesConfig};
+
+use crate::ArenaProtocolContract;
+
+pub const PROTOCOL_FEE_BPS: u32 = 250;
+
+pub fn arena() -> AccountId {
+    "arena.near".parse().unwrap()
+}
+
+pub fn token() -> AccountId {
+    "token.near".parse().unwrap()
+}
+
+pub fn protocol() -> AccountId {
+    "protocol.near".parse().unwrap()
+}
+
+pub fn alice() -> AccountId {
+    accounts(0)
+}
+
+pub fn bob() -> AccountId {
+    accounts(1)
+}
+
+pub fn charlie() -> AccountId {
+    accounts(2)
+}
+
+pub fn dave() -> AccountId {
+    accounts(3)
+}
+
+pub fn context(predecessor: AccountId, block_timestamp: u64) {
+    testing_env!(VMContextBuilder::new()
+        .current_account_id(arena())
+        .predecessor_account_id(predecessor)
+        .block_timestamp(block_timestamp)
+        .build());
+}
+
+// A callback of the contract to itself, seeing `result` as the result of its promise
+pub fn callback_context(result: PromiseResult) {
+    testing_env!(
+        VMContextBuilder::new()
+            .current_account_id(arena())
+            .predecessor_account_id(arena())
+            .block_timestamp(env::block_timestamp())
+            .build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![result]
+    );
+}
+
+pub fn contract() -> ArenaProtocolContract {
+    context(arena(), 0);
+    ArenaProtocolContract::init(protocol(), token(), PROTOCOL_FEE_BPS)
+}
+
+// `amount` of the default token sent with ft_transfer_call
+pub fn transfer(
+    contract: &mut ArenaProtocolContract,
+    sender: AccountId,
+    amount: u128,
+    msg: String,
+) -> PromiseOrValue<U128> {
+    context(token(), env::block_timestamp());
+    contract.ft_on_transfer(sender, U128(amount), msg)
+}
+
+// A MultiPlayer challenge between `participants`, with the extra JSON fields in `extra`
+pub fn challenge_json(participants: &[AccountId], extra: &str) -> String {
+    let participants: Vec<String> = participants
+        .iter()
+        .map(|account_id| format!(r#"{{"account_id":"{}"}}"#, account_id))
+        .collect();
+    format!(
+        r#"{{"link":"game","format":"MultiPlayer","participants":[{}]{}}}"#,
+        participants.join(","),
+        extra
+    )
+}
+
+// The first participant creates the challenge and backs themselves with `stake`.
+// Returns the id of the new challenge.
+pub fn create_challenge(
+    contract: &mut ArenaProtocolContract,
+    participants: &[AccountId],
+    stake: u128,
+    extra: &str,
+) -> u32 {
+    let msg = format!(
+        r#"{{"action":"AddChallengeAndPlaceBet","participant":"{}","challenge":{}}}"#,
+        participants[0],
+        challenge_json(participants, extra)
+    );
+    transfer(contract, participants[0].clone(), stake, msg);
+    contract.get_challenge_counter() - 1
+}
+
+pub fn accept(
+    contract: &mut ArenaProtocolContract,
+    account_id: AccountId,
+    challenge_id: u32,
+    stake: u128,
+) {
+    let msg = format!(
+        r#"{{"action":"AcceptChallenge","challenge_id":{}}}"#,
+        challenge_id
+    );
+    transfer(contract, account_id, stake, msg);
+}
+
+// A challenge every participant has accepted with `stake`
+pub fn live_challenge(
+    contract: &mut ArenaProtocolContract,
+    participants: &[AccountId],
+    stake: u128,
+    extra: &str,
+) -> u32 {
+    let challenge_id = create_challenge(contract, participants, stake, extra);
+    for participant in &participants[1..] {
+        accept(contract, participant.clone(), challenge_id, stake);
+    }
+    challenge_id
+}
+
+pub fn bet(
+    contract: &mut ArenaProtocolContract,
+    account_id: AccountId,
+    challenge_id: u32,
+    participant: AccountId,
+    amount: u128,
+) {
+    let msg = format!(
+        r#"{{"action":"PlaceBetOnly","participant":"{}","challenge_id":{}}}"#,
+        participant, challenge_id
+    );
+    transfer(contract, account_id, amount, msg);
+}
+
+pub fn vote(
+    contract: &mut ArenaProtocolContract,
+    voter: AccountId,
+    challenge_id: u32,
+    ranking: Vec<AccountId>,
+) {
+    context(voter, env::block_timestamp());
+    contract.update_winner_by_challenge(challenge_id, ranking);
+}
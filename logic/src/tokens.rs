@@ -0,0 +1,207 @@
+use near_sdk::json_types::U128;
+use near_sdk::{near, near_bindgen, require, AccountId};
+
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// What the contract owes and has earned in one NEP-141 token
+#[near(serializers = [borsh])]
+#[derive(Clone, Debug, Default)]
+pub struct TokenLedger {
+    // Open stakes, unclaimed winnings, dispute bonds and failed refunds
+    pub total_owed: u128,
+    // Fees accrued for and withdrawn by the protocol account
+    pub accrued_protocol_fees: u128,
+    pub withdrawn_protocol_fees: u128,
+}
+
+// Accounting of one token, as returned by the views
+#[near(serializers = [json])]
+pub struct TokenBalances {
+    pub token_id: AccountId,
+    pub supported: bool,
+    pub total_owed: U128,
+    pub accrued_protocol_fees: U128,
+    pub withdrawn_protocol_fees: U128,
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Public Method - but only callable by env::current_account_id()
+    // allows new challenges to be denominated in a NEP-141 token
+    #[private]
+    pub fn add_supported_token(&mut self, token_id: AccountId) {
+        require!(
+            self.supported_tokens.insert(&token_id),
+            "The token is already supported"
+        );
+    }
+
+    // Public Method - but only callable by env::current_account_id()
+    // stops new challenges in a token, challenges that already use it play out as usual
+    #[private]
+    pub fn remove_supported_token(&mut self, token_id: AccountId) {
+        require!(
+            self.supported_tokens.remove(&token_id),
+            "The token is not supported"
+        );
+    }
+
+    // Method to get the tokens new challenges can be denominated in
+    pub fn get_supported_tokens(&self) -> Vec<AccountId> {
+        self.supported_tokens.to_vec()
+    }
+
+    // Method to get the accounting of every token the contract has handled
+    pub fn get_token_balances(&self) -> Vec<TokenBalances> {
+        self.token_ledgers
+            .iter()
+            .map(|(token_id, ledger)| TokenBalances {
+                supported: self.supported_tokens.contains(&token_id),
+                token_id,
+                total_owed: U128(ledger.total_owed),
+                accrued_protocol_fees: U128(ledger.accrued_protocol_fees),
+                withdrawn_protocol_fees: U128(ledger.withdrawn_protocol_fees),
+            })
+            .collect()
+    }
+
+    // The token a view or an admin call is about, `ft_contract` if none is given
+    pub(crate) fn internal_token_or_default(&self, token_id: Option<AccountId>) -> AccountId {
+        token_id.unwrap_or_else(|| self.ft_contract.clone())
+    }
+
+    pub(crate) fn assert_supported_token(&self, token_id: &AccountId) {
+        require!(
+            self.supported_tokens.contains(token_id),
+            format!("The token {} is not supported", token_id)
+        );
+    }
+
+    pub(crate) fn internal_ledger(&self, token_id: &AccountId) -> TokenLedger {
+        self.token_ledgers.get(token_id).unwrap_or_default()
+    }
+
+    pub(crate) fn internal_update_ledger(
+        &mut self,
+        token_id: &AccountId,
+        update: impl FnOnce(&mut TokenLedger),
+    ) {
+        let mut ledger = self.internal_ledger(token_id);
+        update(&mut ledger);
+        self.token_ledgers.insert(token_id, &ledger);
+    }
+
+    pub(crate) fn internal_add_owed(&mut self, token_id: &AccountId, amount: u128) {
+        self.internal_update_ledger(token_id, |ledger| {
+            ledger.total_owed = ledger.total_owed.saturating_add(amount)
+        });
+    }
+
+    pub(crate) fn internal_sub_owed(&mut self, token_id: &AccountId, amount: u128) {
+        self.internal_update_ledger(token_id, |ledger| {
+            ledger.total_owed = ledger.total_owed.saturating_sub(amount)
+        });
+    }
+
+    // Keep a failed transfer until the account retries it, per token
+    pub(crate) fn internal_add_pending_refund(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: u128,
+    ) {
+        let key = (account_id.clone(), token_id.clone());
+        let pending = self.pending_refunds.get(&key).unwrap_or(0);
+        self.pending_refunds
+            .insert(&key, &pending.saturating_add(amount));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::U128;
+    use near_sdk::AccountId;
+
+    use crate::test_utils::*;
+    use crate::ArenaProtocolContract;
+
+    fn usdc() -> AccountId {
+        "usdc.near".parse().unwrap()
+    }
+
+    // 100 usdc sent with ft_transfer_call
+    fn transfer_usdc(contract: &mut ArenaProtocolContract, sender: AccountId, msg: String) {
+        context(usdc(), 0);
+        contract.ft_on_transfer(sender, U128(100), msg);
+    }
+
+    // Alice challenges bob for 100 usdc
+    fn usdc_challenge(contract: &mut ArenaProtocolContract) {
+        let msg = format!(
+            r#"{{"action":"AddChallengeAndPlaceBet","participant":"{}","challenge":{}}}"#,
+            alice(),
+            challenge_json(&[alice(), bob()], "")
+        );
+        transfer_usdc(contract, alice(), msg);
+    }
+
+    #[test]
+    fn tokens_are_added_and_removed() {
+        let mut contract = contract();
+        assert_eq!(contract.get_supported_tokens(), vec![token()]);
+
+        contract.add_supported_token(usdc());
+        assert_eq!(contract.get_supported_tokens(), vec![token(), usdc()]);
+        contract.remove_supported_token(token());
+        assert_eq!(contract.get_supported_tokens(), vec![usdc()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The token is already supported")]
+    fn token_cannot_be_added_twice() {
+        let mut contract = contract();
+        contract.add_supported_token(token());
+    }
+
+    #[test]
+    #[should_panic(expected = "The token usdc.near is not supported")]
+    fn challenges_need_a_supported_token() {
+        let mut contract = contract();
+        usdc_challenge(&mut contract);
+    }
+
+    #[test]
+    fn every_token_keeps_its_own_ledger() {
+        let mut contract = contract();
+        contract.add_supported_token(usdc());
+        usdc_challenge(&mut contract);
+        create_challenge(&mut contract, &[alice(), bob()], 50, "");
+
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.token, usdc());
+        assert_eq!(contract.get_total_owed(Some(usdc())), U128(100));
+        assert_eq!(contract.get_total_owed(None), U128(50));
+    }
+
+    #[test]
+    fn removed_token_keeps_its_challenges_going() {
+        let mut contract = contract();
+        contract.add_supported_token(usdc());
+        usdc_challenge(&mut contract);
+        context(arena(), 0);
+        contract.remove_supported_token(usdc());
+
+        let msg = r#"{"action":"AcceptChallenge","challenge_id":1}"#.to_string();
+        transfer_usdc(&mut contract, bob(), msg);
+        assert_eq!(contract.get_total_owed(Some(usdc())), U128(200));
+
+        let balances = contract.get_token_balances();
+        let usdc_balances = balances
+            .iter()
+            .find(|balances| balances.token_id == usdc())
+            .unwrap();
+        assert!(!usdc_balances.supported);
+        assert_eq!(usdc_balances.total_owed, U128(200));
+    }
+}
@@ -0,0 +1,416 @@
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near, near_bindgen, require, AccountId};
+
+use crate::challenge::{Challenge, ChallengeState, VotingOutcome, VotingPolicy};
+use crate::events::ArenaEvent;
+use crate::ArenaProtocolContract;
+use crate::ArenaProtocolContractExt;
+
+// A row of the standings table of a challenge
+#[near(serializers = [json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub participant: AccountId,
+    // 1 for the leader, tied participants share a position
+    pub position: u8,
+    // Borda points, weighted by the voting policy
+    pub points: U128,
+    pub first_place_votes: U128,
+}
+
+// Borda count: with N participants the first place of a ranking scores N points,
+// the second N - 1, and so on
+pub fn borda_points(participant_count: usize, position: usize) -> u128 {
+    participant_count.saturating_sub(position) as u128
+}
+
+#[near_bindgen]
+impl ArenaProtocolContract {
+    // Method to get the standings of a challenge, best first
+    pub fn get_standings(&self, challenge_id: u32) -> Vec<Standing> {
+        self.internal_standings(&self.internal_get_challenge(challenge_id))
+    }
+
+    // Method for a participant to replay a challenge whose voters did not agree.
+    // The votes are wiped, the stakes stay in escrow and the challenge is Ongoing again,
+    // with new phase deadlines. A phase without one stays open-ended.
+    pub fn rematch(
+        &mut self,
+        challenge_id: u32,
+        betting_closes_at: Option<U64>,
+        voting_closes_at: Option<U64>,
+        claim_expires_at: Option<U64>,
+    ) {
+        let account_id = env::predecessor_account_id();
+        let mut challenge = self.internal_get_challenge(challenge_id);
+
+        Self::assert_challenge_state(
+            &challenge,
+            &[ChallengeState::VotingFinished],
+            "Only a finished vote can be replayed",
+        );
+        require!(
+            challenge.outcome == Some(VotingOutcome::NoConsensus),
+            "Only a challenge without consensus can be replayed"
+        );
+        require!(
+            challenge.side_of(&account_id).is_some(),
+            "Only participants can ask for a rematch"
+        );
+
+        if let Some(mut participant_map) = self.winners.remove(&challenge_id) {
+            participant_map.clear();
+        }
+        if let Some(mut voted_accounts) = self.voted_accounts.remove(&challenge_id) {
+            voted_accounts.clear();
+        }
+
+        challenge.reschedule(
+            betting_closes_at,
+            voting_closes_at,
+            claim_expires_at,
+            env::block_timestamp(),
+        );
+        challenge.outcome = None;
+        challenge.voted_weight = U128(0);
+        for participant in challenge.participants.iter_mut() {
+            participant.first_place_votes = U128(0);
+        }
+        self.challenges.insert(&challenge_id, &challenge);
+        self.update_set_challenge_state(challenge_id, ChallengeState::Ongoing);
+
+        ArenaEvent::RematchRequested {
+            challenge_id,
+            account_id,
+        }
+        .emit();
+    }
+
+    // The account a vote is recorded under and the weight it carries, following the
+    // voting policy of the challenge. Panics if the account may not vote.
+    pub(crate) fn internal_voter(
+        &self,
+        challenge: &Challenge,
+        account_id: &AccountId,
+    ) -> (AccountId, u128) {
+        let voter = match challenge.voting_policy {
+            // Players vote for their side, so a team only gets one vote
+            VotingPolicy::ParticipantsOnly => challenge
+                .side_of(account_id)
+                .expect("Only participants can vote on this challenge")
+                .account_id
+                .clone(),
+            VotingPolicy::StakeWeighted | VotingPolicy::OneVotePerBettor => account_id.clone(),
+        };
+
+        let weight = self.internal_vote_weight(challenge, &voter);
+        assert!(weight > 0, "Only accounts that placed a bet can vote");
+        (voter, weight)
+    }
+
+    // Weight of the vote recorded under `voter`
+    pub(crate) fn internal_vote_weight(&self, challenge: &Challenge, voter: &AccountId) -> u128 {
+        match challenge.voting_policy {
+            VotingPolicy::ParticipantsOnly => 1,
+            // Only the voter's own bets are read, whatever the number of bettors
+            VotingPolicy::StakeWeighted => self.internal_stake_of(challenge.id, voter),
+            VotingPolicy::OneVotePerBettor => {
+                (self.internal_stake_of(challenge.id, voter) > 0) as u128
+            }
+        }
+    }
+
+    // Number of votes after which voting on the challenge is finished: one per participant,
+    // or one per bettor when bettors vote
+    pub(crate) fn internal_eligible_voter_count(&self, challenge: &Challenge) -> u64 {
+        match challenge.voting_policy {
+            VotingPolicy::ParticipantsOnly => challenge.participants.len() as u64,
+            VotingPolicy::StakeWeighted | VotingPolicy::OneVotePerBettor => self
+                .bets
+                .get(&challenge.id)
+                .map_or(0, |self_map| self_map.len()),
+        }
+    }
+
+    // Close the vote: fix the outcome and move the challenge to VotingFinished
+    pub(crate) fn internal_finish_voting(&mut self, challenge_id: u32) {
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        // An oracle result is final, votes decide everything else
+        let outcome = match &challenge.oracle {
+            Some(_) => challenge
+                .outcome
+                .clone()
+                .expect("Only the oracle can decide this challenge"),
+            None => self.internal_voting_outcome(&challenge),
+        };
+        env::log_str(&format!(
+            "Voting on challenge {} finished: {:?}",
+            challenge_id, outcome
+        ));
+
+        challenge.outcome = Some(outcome);
+        // The result can be contested for a while before anything is paid
+        let now = env::block_timestamp();
+        challenge.dispute_deadline = challenge
+            .dispute_terms
+            .as_ref()
+            .map(|terms| U64(now.saturating_add(terms.period.0)));
+        challenge.keep_claims_open(
+            challenge
+                .dispute_deadline
+                .map_or(now, |deadline| deadline.0),
+        );
+        self.challenges.insert(&challenge_id, &challenge);
+        self.update_set_challenge_state(challenge_id, ChallengeState::VotingFinished);
+    }
+
+    // Replace the votes of a challenge with a binding ranking, best first. The ranking
+    // becomes the standings, with its first participant as the decided winner.
+    pub(crate) fn internal_set_result(&mut self, challenge_id: u32, ranking: &[AccountId]) {
+        let mut challenge = self.internal_get_challenge(challenge_id);
+        challenge.assert_valid_ranking(ranking);
+
+        let mut unique_prefix = challenge_id.to_be_bytes().to_vec();
+        unique_prefix.push(b'z'); // Add a byte to identify winners
+
+        let mut participant_map = self
+            .winners
+            .get(&challenge_id)
+            .unwrap_or_else(|| UnorderedMap::new(unique_prefix));
+        participant_map.clear();
+        for (position, participant) in ranking.iter().enumerate() {
+            participant_map.insert(
+                participant,
+                &borda_points(challenge.participants.len(), position),
+            );
+        }
+        self.winners.insert(&challenge_id, &participant_map);
+
+        for participant in challenge.participants.iter_mut() {
+            participant.first_place_votes = U128(0);
+        }
+        challenge.add_first_place_votes(&ranking[0], 1);
+        // The binding ranking is the only vote that counts
+        challenge.voted_weight = U128(1);
+        challenge.outcome = Some(VotingOutcome::Decided(ranking[0].clone()));
+        challenge.updated_at = U64(env::block_timestamp());
+        self.challenges.insert(&challenge_id, &challenge);
+
+        env::log_str(&format!(
+            "Result of challenge {} set to {:?}",
+            challenge_id, ranking
+        ));
+    }
+
+    // Apply the consensus rule of the challenge to the votes cast so far: the leader of
+    // the standings wins if enough vote weight ranked it first. A tie is never a consensus.
+    pub(crate) fn internal_voting_outcome(&self, challenge: &Challenge) -> VotingOutcome {
+        let standings = self.internal_standings(challenge);
+        let leaders: Vec<&Standing> = standings
+            .iter()
+            .filter(|standing| standing.position == 1)
+            .collect();
+
+        match leaders.as_slice() {
+            [leader]
+                if challenge
+                    .consensus_rule
+                    .is_met(leader.first_place_votes.0, challenge.voted_weight.0) =>
+            {
+                VotingOutcome::Decided(leader.participant.clone())
+            }
+            _ => VotingOutcome::NoConsensus,
+        }
+    }
+
+    // Every participant that was ranked, sorted by points, ties broken by first places.
+    // Participants with the same points and first places share a position.
+    pub(crate) fn internal_standings(&self, challenge: &Challenge) -> Vec<Standing> {
+        let Some(participant_map) = self.winners.get(&challenge.id) else {
+            return Vec::new();
+        };
+
+        let mut standings: Vec<Standing> = participant_map
+            .iter()
+            .map(|(participant, points)| Standing {
+                first_place_votes: challenge
+                    .participant(&participant)
+                    .map_or(U128(0), |entry| entry.first_place_votes),
+                participant,
+                position: 0,
+                points: U128(points),
+            })
+            .collect();
+        standings.sort_by_key(|standing| {
+            std::cmp::Reverse((standing.points.0, standing.first_place_votes.0))
+        });
+
+        for index in 0..standings.len() {
+            standings[index].position = match index.checked_sub(1) {
+                Some(previous)
+                    if standings[previous].points == standings[index].points
+                        && standings[previous].first_place_votes
+                            == standings[index].first_place_votes =>
+                {
+                    standings[previous].position
+                }
+                _ => (index + 1).min(u8::MAX as usize) as u8,
+            };
+        }
+        standings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::{U128, U64};
+
+    use super::borda_points;
+    use crate::challenge::{ChallengeState, VotingOutcome};
+    use crate::test_utils::*;
+
+    const STAKE_WEIGHTED: &str = r#","voting_policy":"StakeWeighted","consensus_rule":"Majority""#;
+    const DISPUTABLE: &str =
+        r#","dispute_terms":{"period":"50","bond":"10","arbiter":"protocol.near"}"#;
+
+    // Alice and bob each rank themselves first, so the vote ends without consensus
+    fn deadlocked(extra: &str) -> crate::ArenaProtocolContract {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, extra);
+        vote(&mut contract, alice(), 1, vec![alice(), bob()]);
+        vote(&mut contract, bob(), 1, vec![bob(), alice()]);
+        contract
+    }
+
+    #[test]
+    fn voted_weight_adds_up_the_votes_cast() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, STAKE_WEIGHTED);
+        bet(&mut contract, dave(), 1, alice(), 300);
+
+        vote(&mut contract, alice(), 1, vec![alice(), bob()]);
+        vote(&mut contract, bob(), 1, vec![bob(), alice()]);
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.voted_weight, U128(200));
+        assert_eq!(challenge.state, ChallengeState::Voting);
+
+        // 400 of 500 ranked alice first
+        vote(&mut contract, dave(), 1, vec![alice()]);
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.voted_weight, U128(500));
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(alice())));
+    }
+
+    #[test]
+    #[should_panic(expected = "Voting on this challenge opens once betting has closed")]
+    fn votes_wait_for_betting_to_close() {
+        let mut contract = contract();
+        live_challenge(
+            &mut contract,
+            &[alice(), bob()],
+            100,
+            r#","betting_closes_at":"100""#,
+        );
+        context(alice(), 99);
+        vote(&mut contract, alice(), 1, vec![alice()]);
+    }
+
+    #[test]
+    fn first_vote_after_betting_closes_starts_voting() {
+        let mut contract = contract();
+        live_challenge(
+            &mut contract,
+            &[alice(), bob()],
+            100,
+            r#","betting_closes_at":"100""#,
+        );
+        context(alice(), 100);
+        vote(&mut contract, alice(), 1, vec![alice()]);
+        assert_eq!(contract.get_challenge(1), Some(ChallengeState::Voting));
+    }
+
+    #[test]
+    fn rematch_gets_new_deadlines_and_a_new_dispute_window() {
+        let mut contract = deadlocked(DISPUTABLE);
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.outcome, Some(VotingOutcome::NoConsensus));
+        assert_eq!(challenge.dispute_deadline, Some(U64(50)));
+
+        context(alice(), 10);
+        contract.rematch(1, Some(U64(100)), Some(U64(200)), None);
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.state, ChallengeState::Ongoing);
+        assert_eq!(challenge.betting_closes_at, Some(U64(100)));
+        assert_eq!(challenge.voting_closes_at, Some(U64(200)));
+        assert_eq!(challenge.dispute_deadline, None);
+        assert_eq!(challenge.voted_weight, U128(0));
+        assert_eq!(challenge.outcome, None);
+
+        // The replayed vote opens a dispute window of its own
+        context(alice(), 150);
+        vote(&mut contract, alice(), 1, vec![alice(), bob()]);
+        vote(&mut contract, bob(), 1, vec![alice(), bob()]);
+        let challenge = contract.get_challenge_details(1).unwrap();
+        assert_eq!(challenge.outcome, Some(VotingOutcome::Decided(alice())));
+        assert_eq!(challenge.dispute_deadline, Some(U64(200)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only participants can ask for a rematch")]
+    fn only_participants_can_ask_for_a_rematch() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob()], 100, "");
+        bet(&mut contract, charlie(), 1, alice(), 100);
+        vote(&mut contract, alice(), 1, vec![alice(), bob()]);
+        vote(&mut contract, bob(), 1, vec![bob(), alice()]);
+
+        // Backing a participant does not make charlie one
+        context(charlie(), 10);
+        contract.rematch(1, None, None, None);
+    }
+
+    #[test]
+    fn borda_points_count_down_from_the_number_of_participants() {
+        let points: Vec<u128> = (0..4).map(|position| borda_points(4, position)).collect();
+        assert_eq!(points, vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn standings_break_equal_points_on_first_places() {
+        let mut contract = contract();
+        live_challenge(&mut contract, &[alice(), bob(), charlie()], 100, "");
+        vote(&mut contract, alice(), 1, vec![alice(), charlie(), bob()]);
+        vote(&mut contract, bob(), 1, vec![bob(), alice(), charlie()]);
+        // Leaving alice out scores her nothing on this ballot
+        vote(&mut contract, charlie(), 1, vec![bob(), charlie()]);
+
+        let table: Vec<_> = contract
+            .get_standings(1)
+            .into_iter()
+            .map(|standing| {
+                (
+                    standing.participant,
+                    standing.position,
+                    standing.points.0,
+                    standing.first_place_votes.0,
+                )
+            })
+            .collect();
+        assert_eq!(
+            table,
+            vec![(bob(), 1, 7, 2), (alice(), 2, 5, 1), (charlie(), 3, 5, 0),]
+        );
+    }
+
+    #[test]
+    fn full_tie_shares_the_first_position() {
+        let contract = deadlocked("");
+        let positions: Vec<u8> = contract
+            .get_standings(1)
+            .iter()
+            .map(|standing| standing.position)
+            .collect();
+        assert_eq!(positions, vec![1, 1]);
+    }
+}
@@ -0,0 +1,72 @@
+// Bare bones NEP-141 token for the sandbox tests. Anyone can send any amount with
+// ft_transfer_call, which mints it for the receiver, and ft_transfer fails while `failing`
+// is set, so the tests can check what the arena does when a payout or a refund does not
+// go through.
+use near_sdk::json_types::U128;
+use near_sdk::store::LookupMap;
+use near_sdk::{env, ext_contract, near, require, AccountId, Gas, PanicOnDefault, Promise};
+
+const GAS_FOR_FT_ON_TRANSFER: Gas = Gas::from_tgas(100);
+
+// Only used through the generated `ext_ft_receiver` module
+#[allow(dead_code)]
+#[ext_contract(ext_ft_receiver)]
+trait FungibleTokenReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128;
+}
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct FailingFt {
+    failing: bool,
+    balances: LookupMap<AccountId, u128>,
+}
+
+#[near]
+impl FailingFt {
+    #[init]
+    pub fn new() -> Self {
+        Self {
+            failing: false,
+            balances: LookupMap::new(b"b"),
+        }
+    }
+
+    pub fn set_failing(&mut self, failing: bool) {
+        self.failing = failing;
+    }
+
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        let _ = memo;
+        require!(!self.failing, "ft_transfer is switched off");
+        let sender_id = env::predecessor_account_id();
+        let sender_balance = self.balances.get(&sender_id).copied().unwrap_or(0);
+        require!(sender_balance >= amount.0, "Not enough balance");
+        self.balances.insert(sender_id, sender_balance - amount.0);
+        let balance = self.balances.get(&receiver_id).copied().unwrap_or(0);
+        self.balances.insert(receiver_id, balance + amount.0);
+    }
+
+    // Mints the amount out of thin air for the receiver and tells it about the transfer
+    #[payable]
+    pub fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> Promise {
+        let _ = memo;
+        let balance = self.balances.get(&receiver_id).copied().unwrap_or(0);
+        self.balances
+            .insert(receiver_id.clone(), balance + amount.0);
+        ext_ft_receiver::ext(receiver_id)
+            .with_static_gas(GAS_FOR_FT_ON_TRANSFER)
+            .ft_on_transfer(env::predecessor_account_id(), amount, msg)
+    }
+
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        U128(self.balances.get(&account_id).copied().unwrap_or(0))
+    }
+}
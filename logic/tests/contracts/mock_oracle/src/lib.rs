@@ -0,0 +1,32 @@
+// Oracle for the sandbox tests: whatever ranking the tests hand it is reported to the
+// arena as the result of a challenge, the way a sports or event feed would.
+use near_sdk::{ext_contract, near, AccountId, Gas, PanicOnDefault, Promise};
+
+const GAS_FOR_RESOLVE: Gas = Gas::from_tgas(100);
+
+// Only used through the generated `ext_arena` module
+#[allow(dead_code)]
+#[ext_contract(ext_arena)]
+trait Arena {
+    fn resolve_with_oracle(&mut self, challenge_id: u32, standings: Vec<AccountId>);
+}
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct MockOracle {
+    arena: AccountId,
+}
+
+#[near]
+impl MockOracle {
+    #[init]
+    pub fn new(arena: AccountId) -> Self {
+        Self { arena }
+    }
+
+    pub fn report(&mut self, challenge_id: u32, standings: Vec<AccountId>) -> Promise {
+        ext_arena::ext(self.arena.clone())
+            .with_static_gas(GAS_FOR_RESOLVE)
+            .resolve_with_oracle(challenge_id, standings)
+    }
+}
@@ -0,0 +1,425 @@
+// End to end tests against a local sandbox node, with mock contracts standing in for the
+// NEP-141 token. They need the near-sandbox binary and cargo-near to build the wasm files:
+//
+//     cargo test --test sandbox -- --ignored
+use near_workspaces::network::Sandbox;
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, Contract, Worker};
+use serde_json::{json, Value};
+
+const STAKE: u128 = 100;
+
+struct Arena {
+    worker: Worker<Sandbox>,
+    arena: Contract,
+    token: Contract,
+    alice: Account,
+    bob: Account,
+}
+
+// Deploy the arena with the failing token as its default token
+async fn setup() -> Result<Arena, Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+
+    let token_wasm = near_workspaces::compile_project("./tests/contracts/failing_ft").await?;
+    let token = worker.dev_deploy(&token_wasm).await?;
+    token.call("new").transact().await?.into_result()?;
+
+    let arena_wasm = near_workspaces::compile_project("./").await?;
+    let arena = worker.dev_deploy(&arena_wasm).await?;
+    arena
+        .call("init")
+        .args_json(json!({
+            "new_protocol_account": arena.id(),
+            "new_ft_contract": token.id(),
+            "protocol_fee_bps": 0,
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    Ok(Arena {
+        worker,
+        arena,
+        token,
+        alice,
+        bob,
+    })
+}
+
+// Send tokens to the arena with a message for ft_on_transfer
+async fn transfer_call(
+    env: &Arena,
+    sender: &Account,
+    amount: u128,
+    msg: Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    sender
+        .call(env.token.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": env.arena.id(),
+            "amount": amount.to_string(),
+            "msg": msg.to_string(),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+// Alice challenges Bob with a stake, Bob accepts with the same stake. Returns the id.
+async fn create_accepted_challenge(
+    env: &Arena,
+    extra: Value,
+) -> Result<u32, Box<dyn std::error::Error>> {
+    let challenge_id: u32 = env.arena.view("get_challenge_counter").await?.json()?;
+
+    let mut challenge = json!({
+        "link": "https://example.com/game",
+        "format": "OneVsOne",
+        "participants": [
+            { "account_id": env.alice.id() },
+            { "account_id": env.bob.id() },
+        ],
+    });
+    if let (Some(challenge), Some(extra)) = (challenge.as_object_mut(), extra.as_object()) {
+        challenge.extend(extra.clone());
+    }
+    transfer_call(
+        env,
+        &env.alice,
+        STAKE,
+        json!({
+            "action": "AddChallengeAndPlaceBet",
+            "participant": env.alice.id(),
+            "challenge": challenge,
+        }),
+    )
+    .await?;
+    transfer_call(
+        env,
+        &env.bob,
+        STAKE,
+        json!({ "action": "AcceptChallenge", "challenge_id": challenge_id }),
+    )
+    .await?;
+    Ok(challenge_id)
+}
+
+// Both players agree that Alice won
+async fn vote_alice_won(env: &Arena, challenge_id: u32) -> Result<(), Box<dyn std::error::Error>> {
+    for voter in [&env.alice, &env.bob] {
+        voter
+            .call(env.arena.id(), "update_winner_by_challenge")
+            .args_json(json!({
+                "challenge_id": challenge_id,
+                "participants": [env.alice.id(), env.bob.id()],
+            }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    Ok(())
+}
+
+// Deploy the mock oracle, reporting to the arena
+async fn deploy_oracle(env: &Arena) -> Result<Contract, Box<dyn std::error::Error>> {
+    let oracle_wasm = near_workspaces::compile_project("./tests/contracts/mock_oracle").await?;
+    let oracle = env.worker.dev_deploy(&oracle_wasm).await?;
+    oracle
+        .call("new")
+        .args_json(json!({ "arena": env.arena.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(oracle)
+}
+
+async fn challenge_state(
+    env: &Arena,
+    challenge_id: u32,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let state: String = env
+        .arena
+        .view("get_challenge")
+        .args_json(json!({ "challenge_id": challenge_id }))
+        .await?
+        .json()?;
+    Ok(state)
+}
+
+async fn set_failing(env: &Arena, failing: bool) -> Result<(), Box<dyn std::error::Error>> {
+    env.token
+        .call("set_failing")
+        .args_json(json!({ "failing": failing }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+async fn total_owed(env: &Arena) -> Result<u128, Box<dyn std::error::Error>> {
+    let owed: String = env
+        .arena
+        .view("get_total_owed")
+        .args_json(json!({ "token_id": env.token.id() }))
+        .await?
+        .json()?;
+    Ok(owed.parse()?)
+}
+
+async fn token_balance(env: &Arena, account: &Account) -> Result<u128, Box<dyn std::error::Error>> {
+    let balance: String = env
+        .worker
+        .view(env.token.id(), "ft_balance_of")
+        .args_json(json!({ "account_id": account.id() }))
+        .await?
+        .json()?;
+    Ok(balance.parse()?)
+}
+
+#[tokio::test]
+#[ignore = "needs near-sandbox and cargo-near to build the contracts"]
+async fn failed_payout_can_be_claimed_again() -> Result<(), Box<dyn std::error::Error>> {
+    let env = setup().await?;
+    let challenge_id = create_accepted_challenge(&env, json!({})).await?;
+    vote_alice_won(&env, challenge_id).await?;
+    assert_eq!(total_owed(&env).await?, 2 * STAKE);
+
+    // The payout fails, the claim is undone and the winnings are still owed
+    set_failing(&env, true).await?;
+    let outcome = env
+        .alice
+        .call(env.arena.id(), "claim")
+        .args_json(json!({ "challenge_id": challenge_id }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_success());
+    assert!(!outcome.receipt_failures().is_empty());
+    assert!(outcome
+        .logs()
+        .iter()
+        .any(|log| log.contains("payout_failed") && log.contains("\"retryable\":true")));
+
+    let claimed: bool = env
+        .arena
+        .view("has_claimed")
+        .args_json(json!({ "challenge_id": challenge_id, "account_id": env.alice.id() }))
+        .await?
+        .json()?;
+    assert!(!claimed);
+    assert_eq!(total_owed(&env).await?, 2 * STAKE);
+    assert_eq!(token_balance(&env, &env.alice).await?, 0);
+
+    // Once the token works again the same claim pays out the whole pool
+    set_failing(&env, false).await?;
+    env.alice
+        .call(env.arena.id(), "claim")
+        .args_json(json!({ "challenge_id": challenge_id }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(token_balance(&env, &env.alice).await?, 2 * STAKE);
+    assert_eq!(total_owed(&env).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "needs near-sandbox and cargo-near to build the contracts"]
+async fn failed_refund_is_kept_for_retry() -> Result<(), Box<dyn std::error::Error>> {
+    let env = setup().await?;
+    let challenge_id: u32 = env.arena.view("get_challenge_counter").await?.json()?;
+    transfer_call(
+        &env,
+        &env.alice,
+        STAKE,
+        json!({
+            "action": "AddChallengeAndPlaceBet",
+            "participant": env.alice.id(),
+            "challenge": {
+                "link": "https://example.com/game",
+                "format": "OneVsOne",
+                "participants": [
+                    { "account_id": env.alice.id() },
+                    { "account_id": env.bob.id() },
+                ],
+            },
+        }),
+    )
+    .await?;
+
+    // The creator calls it off, but the refund does not go through
+    set_failing(&env, true).await?;
+    env.alice
+        .call(env.arena.id(), "cancel_challenge")
+        .args_json(json!({ "challenge_id": challenge_id }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    let pending: String = env
+        .arena
+        .view("get_pending_refund")
+        .args_json(json!({ "account_id": env.alice.id() }))
+        .await?
+        .json()?;
+    assert_eq!(pending, STAKE.to_string());
+    assert_eq!(total_owed(&env).await?, STAKE);
+
+    set_failing(&env, false).await?;
+    env.alice
+        .call(env.arena.id(), "retry_refund")
+        .args_json(json!({}))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(token_balance(&env, &env.alice).await?, STAKE);
+    assert_eq!(total_owed(&env).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "needs near-sandbox and cargo-near to build the contracts"]
+async fn random_account_cannot_drain_the_pool() -> Result<(), Box<dyn std::error::Error>> {
+    let env = setup().await?;
+    let challenge_id = create_accepted_challenge(&env, json!({})).await?;
+    let stranger = env.worker.dev_create_account().await?;
+
+    // The admin methods are private, and so are the callbacks behind them
+    for (method, args) in [
+        (
+            "admin_withdraw",
+            json!({ "receiver_id": stranger.id(), "amount": (2 * STAKE).to_string() }),
+        ),
+        (
+            "on_admin_withdraw_balance",
+            json!({
+                "receiver_id": stranger.id(),
+                "amount": (2 * STAKE).to_string(),
+                "token_id": env.token.id(),
+            }),
+        ),
+        (
+            "external_call_callback",
+            json!({
+                "receiver_id": stranger.id(),
+                "amount": (2 * STAKE).to_string(),
+                "challenge_id": challenge_id,
+            }),
+        ),
+        ("withdraw_protocol_fees", json!({})),
+    ] {
+        let outcome = stranger
+            .call(env.arena.id(), method)
+            .args_json(args)
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(outcome.is_failure(), "{} succeeded", method);
+    }
+
+    // Not even the contract itself can withdraw what it owes to the players
+    let outcome = env
+        .arena
+        .call("admin_withdraw")
+        .args_json(json!({ "receiver_id": stranger.id(), "amount": "1" }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+
+    assert_eq!(token_balance(&env, &stranger).await?, 0);
+    assert_eq!(
+        token_balance(&env, env.arena.as_account()).await?,
+        2 * STAKE
+    );
+    assert_eq!(total_owed(&env).await?, 2 * STAKE);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "needs near-sandbox and cargo-near to build the contracts"]
+async fn oracle_result_is_paid_out() -> Result<(), Box<dyn std::error::Error>> {
+    let env = setup().await?;
+    let oracle = deploy_oracle(&env).await?;
+    let challenge_id = create_accepted_challenge(&env, json!({ "oracle": oracle.id() })).await?;
+
+    // Players cannot vote on an oracle challenge
+    let outcome = env
+        .alice
+        .call(env.arena.id(), "update_winner_by_challenge")
+        .args_json(json!({ "challenge_id": challenge_id, "participants": [env.alice.id()] }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(outcome.is_failure());
+
+    oracle
+        .call("report")
+        .args_json(json!({
+            "challenge_id": challenge_id,
+            "standings": [env.bob.id(), env.alice.id()],
+        }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(challenge_state(&env, challenge_id).await?, "VotingFinished");
+
+    env.bob
+        .call(env.arena.id(), "claim")
+        .args_json(json!({ "challenge_id": challenge_id }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(token_balance(&env, &env.bob).await?, 2 * STAKE);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "needs near-sandbox and cargo-near to build the contracts"]
+async fn silent_oracle_challenge_is_cancelled() -> Result<(), Box<dyn std::error::Error>> {
+    let env = setup().await?;
+    let oracle = deploy_oracle(&env).await?;
+
+    let now = env.worker.view_block().await?.timestamp();
+    let second = 1_000_000_000u64;
+    let challenge_id = create_accepted_challenge(
+        &env,
+        json!({
+            "oracle": oracle.id(),
+            "betting_closes_at": (now + 60 * second).to_string(),
+            "voting_closes_at": (now + 120 * second).to_string(),
+        }),
+    )
+    .await?;
+
+    // Betting and voting close without the oracle ever reporting
+    env.worker.fast_forward(1_000).await?;
+    for expected in ["Voting", "Cancelled"] {
+        env.alice
+            .call(env.arena.id(), "advance_challenge")
+            .args_json(json!({ "challenge_id": challenge_id }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+        assert_eq!(challenge_state(&env, challenge_id).await?, expected);
+    }
+
+    // Nobody won, the cancel sent everyone their stake back
+    for player in [&env.alice, &env.bob] {
+        assert_eq!(token_balance(&env, player).await?, STAKE);
+    }
+    assert_eq!(total_owed(&env).await?, 0);
+    Ok(())
+}
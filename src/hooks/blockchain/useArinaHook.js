@@ -156,8 +156,6 @@ const useArinaHook = () => {
 
     const handleClaimWinnings = async ({data}) => {
       try {
-        let rank = 1;
-    
         let actionOfCallBack = {
           hook:"useArinaHook",
           method:"handleClaimWinnings",
@@ -169,9 +167,9 @@ const useArinaHook = () => {
 
         const result = await wallet.callMethod({
           contractId: arinaContractId,  // Replace with your contract account ID
-          method: 'claim_winnings',                    // Method to call
-          args: { challenge_id: data.challengeId, rank },   // Arguments for the method
-          gas: '30000000000000',                       // Optional: gas limit (default: 30 TeraGas)
+          method: 'claim',                             // Method to call
+          args: { challenge_id: data.challengeId },    // Arguments for the method
+          gas: '100000000000000',                      // Covers the payout and its callback
           deposit: '0',                                // Optional: deposit amount in yoctoNEAR, if any
         });
     